@@ -0,0 +1,91 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use http::request::HttpRequest;
+use regex::Regex;
+use std::collections::HashMap;
+
+/// The line-by-line regex parser the byte-level parser replaced
+///
+/// Kept as it was apart from compiling the patterns once instead of on every line, not
+/// printing the request and borrowing it instead of taking a `String`, all of which only
+/// made it slower.
+struct RegexParser {
+    request_line: Regex,
+    header: Regex,
+}
+
+impl RegexParser {
+    fn new() -> RegexParser {
+        RegexParser {
+            request_line: Regex::new(r"(GET|POST)\s/[0-9a-zA-z/.]*\sHTTP/(1\.1|1\.2|2\.0)")
+                .unwrap(),
+            header: Regex::new(r"[a-zA-Z-]*:\s[\d\D]+").unwrap(),
+        }
+    }
+
+    fn parse(&self, str: &str) -> (Vec<String>, HashMap<String, String>, Option<String>) {
+        let mut request_line = Vec::new();
+        let mut headers = HashMap::new();
+        let mut body = None;
+
+        for line in str.lines() {
+            match line {
+                l if self.request_line.is_match(l) => {
+                    request_line = l.split(' ').map(String::from).collect();
+                }
+                l if self.header.is_match(l) => {
+                    let colon = l.find(':').unwrap();
+                    let key = l[..colon].trim().to_string();
+                    let value = l[colon + 1..].trim().to_string();
+
+                    headers.entry(key).or_insert(value);
+                }
+                l => {
+                    body = if !l.is_empty() {
+                        Some(l.to_string())
+                    } else {
+                        None
+                    };
+                }
+            }
+        }
+
+        (request_line, headers, body)
+    }
+}
+
+/// A GET with `count` headers of the size browsers send
+fn request(count: usize) -> String {
+    let mut request = String::from("GET /static/app.js HTTP/1.1\r\nHost: localhost:3000\r\n");
+
+    for i in 1..count {
+        request.push_str(&format!(
+            "X-Header-{}: text/html,application/xhtml+xml,application/xml;q=0.9\r\n",
+            i
+        ));
+    }
+
+    request.push_str("\r\n");
+    request
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let regex_parser = RegexParser::new();
+    let mut group = c.benchmark_group("parse");
+
+    for count in [8, 32, 96] {
+        let request = request(count);
+
+        group.throughput(Throughput::Bytes(request.len() as u64));
+        group.bench_with_input(BenchmarkId::new("bytes", count), &request, |b, request| {
+            b.iter(|| HttpRequest::try_from(black_box(request.as_bytes())).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("regex", count), &request, |b, request| {
+            b.iter(|| regex_parser.parse(black_box(request)))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,206 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+/// Size of the reads used to stream `Reader` and `File` bodies
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Http response bodies
+///
+/// include
+/// - no body at all
+/// - bytes already in memory
+/// - a reader or a file, copied to the socket without buffering it whole
+/// - an iterator of chunks produced while sending
+#[derive(Default)]
+pub enum Body {
+    #[default]
+    Empty,
+    Full(Vec<u8>),
+    Reader(Box<dyn Read + Send>),
+    File(File),
+    Stream(Box<dyn Iterator<Item = io::Result<Vec<u8>>> + Send>),
+}
+
+impl Body {
+    /// Create a body from a reader of unknown length
+    pub fn reader(reader: impl Read + Send + 'static) -> Body {
+        Body::Reader(Box::new(reader))
+    }
+
+    /// Create a body from chunks of unknown total length
+    pub fn stream(chunks: impl Iterator<Item = io::Result<Vec<u8>>> + Send + 'static) -> Body {
+        Body::Stream(Box::new(chunks))
+    }
+
+    /// Length in bytes, if it is known before the body is sent
+    pub fn content_length(&self) -> Option<u64> {
+        match self {
+            Body::Empty => Some(0),
+            Body::Full(bytes) => Some(bytes.len() as u64),
+            Body::File(file) => file.metadata().ok().map(|m| m.len()),
+            Body::Reader(_) | Body::Stream(_) => None,
+        }
+    }
+
+    /// Write the body as is, which is right for bodies framed by `Content-Length`
+    /// or by closing the connection
+    ///
+    /// A file is written up to the length it has now, whatever is appended meanwhile.
+    pub fn write_to(self, write_stream: &mut impl Write) -> io::Result<()> {
+        match self {
+            Body::Empty => Ok(()),
+            Body::Full(bytes) => write_stream.write_all(&bytes),
+            Body::Reader(mut reader) => io::copy(&mut reader, write_stream).map(|_| ()),
+            Body::File(file) => {
+                let length = file.metadata()?.len();
+
+                io::copy(&mut file.take(length), write_stream).map(|_| ())
+            }
+            Body::Stream(chunks) => {
+                for chunk in chunks {
+                    write_stream.write_all(&chunk?)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Write the body with the chunked transfer coding (RFC 9112 §7.1)
+    pub fn write_chunked_to(self, write_stream: &mut impl Write) -> io::Result<()> {
+        match self {
+            Body::Empty => {}
+            Body::Full(bytes) => write_chunk(write_stream, &bytes)?,
+            Body::Reader(mut reader) => copy_chunked(&mut reader, write_stream)?,
+            Body::File(mut file) => copy_chunked(&mut file, write_stream)?,
+            Body::Stream(chunks) => {
+                for chunk in chunks {
+                    write_chunk(write_stream, &chunk?)?;
+                }
+            }
+        }
+
+        write_stream.write_all(b"0\r\n\r\n")
+    }
+}
+
+fn copy_chunked(reader: &mut impl Read, write_stream: &mut impl Write) -> io::Result<()> {
+    let mut buffer = [0u8; CHUNK_SIZE];
+
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(len) => write_chunk(write_stream, &buffer[..len])?,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Write one chunk; an empty chunk would end the body, so it is skipped
+fn write_chunk(write_stream: &mut impl Write, chunk: &[u8]) -> io::Result<()> {
+    if chunk.is_empty() {
+        return Ok(());
+    }
+
+    write!(write_stream, "{:x}\r\n", chunk.len())?;
+    write_stream.write_all(chunk)?;
+    write_stream.write_all(b"\r\n")
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Body::Empty => write!(f, "Body::Empty"),
+            Body::Full(bytes) => write!(f, "Body::Full({} bytes)", bytes.len()),
+            Body::Reader(_) => write!(f, "Body::Reader"),
+            Body::File(file) => write!(f, "Body::File({:?})", file),
+            Body::Stream(_) => write!(f, "Body::Stream"),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Body {
+        Body::Full(bytes)
+    }
+}
+
+impl From<String> for Body {
+    fn from(str: String) -> Body {
+        Body::Full(str.into_bytes())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(str: &str) -> Body {
+        Body::Full(str.as_bytes().to_vec())
+    }
+}
+
+impl From<File> for Body {
+    fn from(file: File) -> Body {
+        Body::File(file)
+    }
+}
+
+impl From<Option<Vec<u8>>> for Body {
+    fn from(bytes: Option<Vec<u8>>) -> Body {
+        match bytes {
+            Some(bytes) => Body::Full(bytes),
+            None => Body::Empty,
+        }
+    }
+}
+
+#[cfg(test)]
+mod body_test {
+    use super::*;
+
+    #[test]
+    fn test_content_length() {
+        assert_eq!(Body::Empty.content_length(), Some(0));
+        assert_eq!(Body::from("hello").content_length(), Some(5));
+        assert_eq!(Body::reader(io::empty()).content_length(), None);
+        assert_eq!(Body::stream(std::iter::empty()).content_length(), None);
+    }
+
+    #[test]
+    fn test_write_chunked() {
+        let mut written = Vec::new();
+        let chunks = vec![
+            Ok(b"hello".to_vec()),
+            Ok(Vec::new()),
+            Ok(b", world".to_vec()),
+        ];
+
+        Body::stream(chunks.into_iter())
+            .write_chunked_to(&mut written)
+            .unwrap();
+
+        assert_eq!(written, b"5\r\nhello\r\n7\r\n, world\r\n0\r\n\r\n");
+
+        let mut written = Vec::new();
+
+        Body::reader(&b"0123456789abcdefg"[..])
+            .write_chunked_to(&mut written)
+            .unwrap();
+
+        assert_eq!(written, b"11\r\n0123456789abcdefg\r\n0\r\n\r\n");
+    }
+
+    #[test]
+    fn test_stream_error() {
+        let chunks = vec![
+            Ok(b"partial".to_vec()),
+            Err(io::Error::other("export failed")),
+        ];
+        let mut written = Vec::new();
+
+        assert!(Body::stream(chunks.into_iter())
+            .write_chunked_to(&mut written)
+            .is_err());
+        assert_eq!(written, b"7\r\npartial\r\n");
+    }
+}
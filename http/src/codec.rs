@@ -0,0 +1,187 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::request::{HttpRequest, ParseError, ParseStatus, ParserConfig, RequestParser};
+use crate::response::HttpResponse;
+
+/// Server side codec: decodes [`HttpRequest`]s and encodes [`HttpResponse`]s
+///
+/// Wrap a connection in a `tokio_util::codec::Framed` to read requests as a stream and
+/// send responses through a sink. Pipelined requests are decoded one after the other
+/// from the same read buffer. Response bodies are written into the write buffer whole,
+/// files and streams included.
+#[derive(Debug, Default)]
+pub struct HttpCodec {
+    parser: RequestParser,
+}
+
+/// Error of an [`HttpCodec`]
+#[derive(Debug)]
+pub enum CodecError {
+    /// The client sent something that is not a valid request
+    Parse(ParseError),
+    Io(io::Error),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodecError::Parse(e) => write!(f, "{}", e),
+            CodecError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for CodecError {}
+
+impl From<ParseError> for CodecError {
+    fn from(e: ParseError) -> CodecError {
+        CodecError::Parse(e)
+    }
+}
+
+impl From<io::Error> for CodecError {
+    fn from(e: io::Error) -> CodecError {
+        CodecError::Io(e)
+    }
+}
+
+impl HttpCodec {
+    pub fn new() -> HttpCodec {
+        HttpCodec::default()
+    }
+
+    pub fn with_config(config: ParserConfig) -> HttpCodec {
+        HttpCodec {
+            parser: RequestParser::with_config(config),
+        }
+    }
+}
+
+impl Decoder for HttpCodec {
+    type Item = HttpRequest;
+    type Error = CodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<HttpRequest>, CodecError> {
+        match self.parser.parse(src)? {
+            ParseStatus::Complete(request, consumed) => {
+                src.advance(consumed);
+
+                Ok(Some(request))
+            }
+            ParseStatus::Incomplete => Ok(None),
+        }
+    }
+
+    /// A connection closed between requests ends the stream; one closed halfway through
+    /// a request is an error unless only the final empty line is missing
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<HttpRequest>, CodecError> {
+        if let Some(request) = self.decode(src)? {
+            return Ok(Some(request));
+        }
+
+        if src.iter().all(|b| matches!(b, b'\r' | b'\n')) {
+            src.clear();
+
+            return Ok(None);
+        }
+
+        let request = self.parser.finish(src);
+
+        src.clear();
+
+        Ok(Some(request?))
+    }
+}
+
+/// Encoding fails like [`HttpResponse::send`]: a response with an invalid header field
+/// is replaced by a bare 500, and the field is returned as an `InvalidData` error
+impl Encoder<HttpResponse> for HttpCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, response: HttpResponse, dst: &mut BytesMut) -> Result<(), CodecError> {
+        response.send(&mut dst.writer())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod codec_test {
+    use super::*;
+    use crate::request::Method;
+    use crate::response::HttpStatus;
+
+    #[test]
+    fn test_decode_pipelined() {
+        let mut codec = HttpCodec::with_config(ParserConfig::strict());
+        let mut buf =
+            BytesMut::from(&b"GET /a HTTP/1.1\r\nHost: x\r\n\r\nPOST /b HTTP/1.1\r\nHo"[..]);
+
+        let request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(request.mothod, Method::Get);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        buf.extend_from_slice(b"st: x\r\nContent-Length: 2\r\n\r\nhi");
+
+        let request = codec.decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(request.mothod, Method::Post);
+        assert_eq!(request.body, Some(b"hi".to_vec()));
+        assert!(buf.is_empty());
+        assert!(codec.decode_eof(&mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut codec = HttpCodec::with_config(ParserConfig::strict());
+        let mut buf =
+            BytesMut::from(&b"GET / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhi"[..]);
+
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert!(matches!(
+            codec.decode_eof(&mut buf),
+            Err(CodecError::Parse(ParseError::UnexpectedEof))
+        ));
+
+        let mut buf = BytesMut::from(&b"GARBAGE\r\n\r\n"[..]);
+
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(CodecError::Parse(ParseError::BadRequestLine))
+        ));
+    }
+
+    #[test]
+    fn test_encode() {
+        let mut codec = HttpCodec::new();
+        let mut buf = BytesMut::new();
+        let response = HttpResponse::builder()
+            .status(HttpStatus::NOT_FOUND)
+            .text("gone")
+            .header("Date", "Sat, 24 Sep 2022 03:15:53 GMT")
+            .build();
+
+        codec.encode(response, &mut buf).unwrap();
+
+        assert_eq!(
+            &buf[..],
+            b"HTTP/1.1 404 Not Found\r\nContent-Type: text/plain; charset=utf-8\r\nDate: Sat, 24 Sep 2022 03:15:53 GMT\r\nContent-Length: 4\r\n\r\ngone"
+        );
+
+        let response = HttpResponse::builder().header("X-Bad", "a\r\nb").build();
+
+        buf.clear();
+
+        assert!(matches!(
+            codec.encode(response, &mut buf),
+            Err(CodecError::Io(_))
+        ));
+        assert!(buf.starts_with(b"HTTP/1.1 500 Internal Server Error\r\n"));
+    }
+}
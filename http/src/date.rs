@@ -0,0 +1,289 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const LONG_WEEKDAYS: [&str; 7] = [
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// A timestamp as carried by `Date`, `Last-Modified` and `If-Modified-Since`
+///
+/// Http dates have a precision of one second and are always in GMT. They are written as
+/// IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`, and also read in the obsolete
+/// RFC 850 and asctime formats (RFC 9110 §5.6.7).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HttpDate {
+    /// Seconds since the unix epoch
+    secs: u64,
+}
+
+/// A value that is not an http date
+#[derive(Debug, PartialEq)]
+pub struct InvalidDate;
+
+impl fmt::Display for InvalidDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid http date")
+    }
+}
+
+impl Error for InvalidDate {}
+
+impl HttpDate {
+    pub fn now() -> HttpDate {
+        HttpDate::from(SystemTime::now())
+    }
+}
+
+/// Sub-second precision is dropped and times before 1970 are clamped to the epoch
+impl From<SystemTime> for HttpDate {
+    fn from(time: SystemTime) -> HttpDate {
+        let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+
+        HttpDate { secs }
+    }
+}
+
+impl From<HttpDate> for SystemTime {
+    fn from(date: HttpDate) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(date.secs)
+    }
+}
+
+impl fmt::Display for HttpDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let days = self.secs / 86400;
+        let secs_of_day = self.secs % 86400;
+        let (year, month, day) = civil_from_days(days);
+
+        write!(
+            f,
+            "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            WEEKDAYS[(days % 7) as usize],
+            day,
+            MONTHS[month as usize - 1],
+            year,
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60
+        )
+    }
+}
+
+impl FromStr for HttpDate {
+    type Err = InvalidDate;
+
+    fn from_str(s: &str) -> Result<HttpDate, InvalidDate> {
+        parse_imf_fixdate(s)
+            .or_else(|| parse_rfc850(s))
+            .or_else(|| parse_asctime(s))
+            .ok_or(InvalidDate)
+    }
+}
+
+/// `Sun, 06 Nov 1994 08:49:37 GMT`
+fn parse_imf_fixdate(s: &str) -> Option<HttpDate> {
+    let (weekday, rest) = s.split_once(", ")?;
+    let fields: Vec<&str> = rest.split(' ').collect();
+
+    match fields[..] {
+        [day, month, year, time, "GMT"] if WEEKDAYS.contains(&weekday) && year.len() == 4 => {
+            from_fields(number(year, 4)?, month, number(day, 2)?, time)
+        }
+        _ => None,
+    }
+}
+
+/// `Sunday, 06-Nov-94 08:49:37 GMT`
+fn parse_rfc850(s: &str) -> Option<HttpDate> {
+    let (weekday, rest) = s.split_once(", ")?;
+    let fields: Vec<&str> = rest.split(' ').collect();
+
+    match fields[..] {
+        [date, time, "GMT"] if LONG_WEEKDAYS.contains(&weekday) => {
+            let date: Vec<&str> = date.split('-').collect();
+            let [day, month, year] = date[..] else {
+                return None;
+            };
+            // Two digit years are read as 1970-2069, the range we can represent anyway
+            let year = match number(year, 2)? {
+                year @ 70.. => 1900 + year,
+                year => 2000 + year,
+            };
+
+            from_fields(year, month, number(day, 2)?, time)
+        }
+        _ => None,
+    }
+}
+
+/// `Sun Nov  6 08:49:37 1994`
+fn parse_asctime(s: &str) -> Option<HttpDate> {
+    let fields: Vec<&str> = s.split(' ').collect();
+
+    match fields[..] {
+        [weekday, month, "", day, time, year] if WEEKDAYS.contains(&weekday) => {
+            from_fields(number(year, 4)?, month, number(day, 1)?, time)
+        }
+        [weekday, month, day, time, year] if WEEKDAYS.contains(&weekday) => {
+            from_fields(number(year, 4)?, month, number(day, 2)?, time)
+        }
+        _ => None,
+    }
+}
+
+fn from_fields(year: u64, month: &str, day: u64, time: &str) -> Option<HttpDate> {
+    let month = MONTHS.iter().position(|m| *m == month)? as u64 + 1;
+    let time: Vec<&str> = time.split(':').collect();
+    let [hour, minute, second] = time[..] else {
+        return None;
+    };
+    let (hour, minute, second) = (number(hour, 2)?, number(minute, 2)?, number(second, 2)?);
+
+    // Second 60 is a leap second
+    if year < 1970 || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+
+    Some(HttpDate {
+        secs: days * 86400 + hour * 3600 + minute * 60 + second,
+    })
+}
+
+/// A number of exactly `digits` decimal digits
+fn number(s: &str, digits: usize) -> Option<u64> {
+    if s.len() != digits || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    s.parse().ok()
+}
+
+fn is_leap_year(year: u64) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let days_before_year = (1970..year)
+        .map(|y| if is_leap_year(y) { 366 } else { 365 })
+        .sum::<u64>();
+    let days_before_month = (1..month).map(|m| days_in_month(year, m)).sum::<u64>();
+
+    days_before_year + days_before_month + day - 1
+}
+
+/// `(year, month, day)` of a number of days since 1970-01-01
+fn civil_from_days(mut days: u64) -> (u64, u64, u64) {
+    let mut year = 1970;
+
+    loop {
+        let year_len = if is_leap_year(year) { 366 } else { 365 };
+
+        if days < year_len {
+            break;
+        }
+
+        days -= year_len;
+        year += 1;
+    }
+
+    let mut month = 1;
+
+    while days >= days_in_month(year, month) {
+        days -= days_in_month(year, month);
+        month += 1;
+    }
+
+    (year, month, days + 1)
+}
+
+#[cfg(test)]
+mod date_test {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let cases = [
+            (0, "Thu, 01 Jan 1970 00:00:00 GMT"),
+            (784111777, "Sun, 06 Nov 1994 08:49:37 GMT"),
+            (951782400, "Tue, 29 Feb 2000 00:00:00 GMT"),
+            (1663989353, "Sat, 24 Sep 2022 03:15:53 GMT"),
+            (4102444799, "Thu, 31 Dec 2099 23:59:59 GMT"),
+        ];
+
+        for (secs, formatted) in cases {
+            let date = HttpDate::from(UNIX_EPOCH + Duration::from_secs(secs));
+
+            assert_eq!(date.to_string(), formatted);
+            assert_eq!(formatted.parse(), Ok(date));
+        }
+    }
+
+    #[test]
+    fn test_parse_obsolete_formats() {
+        let date: HttpDate = "Sun, 06 Nov 1994 08:49:37 GMT".parse().unwrap();
+
+        assert_eq!("Sunday, 06-Nov-94 08:49:37 GMT".parse(), Ok(date));
+        assert_eq!("Sun Nov  6 08:49:37 1994".parse(), Ok(date));
+        assert_eq!(
+            SystemTime::from(date),
+            UNIX_EPOCH + Duration::from_secs(784111777)
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let cases = [
+            "",
+            "yesterday",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 6 Nov 1994 08:49:37 GMT",
+            "Sun, 31 Nov 1994 08:49:37 GMT",
+            "Sun, 29 Feb 2023 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1969 08:49:37 GMT",
+            "Funday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov 6 08:49:37 1994",
+        ];
+
+        for raw in cases {
+            assert_eq!(raw.parse::<HttpDate>(), Err(InvalidDate), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn test_ordering() {
+        let modified: HttpDate = "Sat, 24 Sep 2022 03:15:53 GMT".parse().unwrap();
+        let since: HttpDate = "Sat, 24 Sep 2022 03:15:54 GMT".parse().unwrap();
+
+        assert!(modified < since);
+    }
+}
@@ -0,0 +1,349 @@
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::date::HttpDate;
+
+/// Http header field name
+///
+/// The spelling it was created with is kept for serialization, while comparison and
+/// hashing ignore ASCII case, so `content-type` and `Content-Type` are the same field.
+#[derive(Debug, Clone)]
+pub struct HeaderName(String);
+
+impl HeaderName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether the name is a `token` (RFC 9110 §5.1)
+    pub fn is_valid(&self) -> bool {
+        !self.0.is_empty() && self.0.bytes().all(is_tchar)
+    }
+}
+
+impl PartialEq for HeaderName {
+    fn eq(&self, other: &HeaderName) -> bool {
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+}
+
+impl Eq for HeaderName {}
+
+impl PartialEq<str> for HeaderName {
+    fn eq(&self, other: &str) -> bool {
+        self.0.eq_ignore_ascii_case(other)
+    }
+}
+
+impl PartialEq<&str> for HeaderName {
+    fn eq(&self, other: &&str) -> bool {
+        self.0.eq_ignore_ascii_case(other)
+    }
+}
+
+impl Hash for HeaderName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for b in self.0.bytes() {
+            state.write_u8(b.to_ascii_lowercase());
+        }
+    }
+}
+
+impl From<&str> for HeaderName {
+    fn from(name: &str) -> HeaderName {
+        HeaderName(name.into())
+    }
+}
+
+impl From<String> for HeaderName {
+    fn from(name: String) -> HeaderName {
+        HeaderName(name)
+    }
+}
+
+impl fmt::Display for HeaderName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A header field that can't be serialized without corrupting the message
+#[derive(Debug, PartialEq)]
+pub enum HeaderError {
+    InvalidName(String),
+    /// The value of the named field contains control characters such as CR or LF
+    InvalidValue(String),
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::InvalidName(name) => write!(f, "invalid header name {:?}", name),
+            HeaderError::InvalidValue(name) => write!(f, "invalid value for header {:?}", name),
+        }
+    }
+}
+
+impl Error for HeaderError {}
+
+/// Http header fields
+///
+/// Fields keep the order they were added in and a name may carry several values, which
+/// is needed for fields like `Set-Cookie` that can't be folded into one line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeaderMap {
+    entries: Vec<(HeaderName, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> HeaderMap {
+        HeaderMap::default()
+    }
+
+    /// First value of `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Every value of `name`, in insertion order
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.entries
+            .iter()
+            .filter(move |(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.entries.iter().any(|(k, _)| k == name)
+    }
+
+    /// Whether a comma separated field such as `Connection` lists `token`, ignoring case
+    pub fn contains_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name)
+            .flat_map(|v| v.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    }
+
+    /// First value of `name` read as a date, e.g. `If-Modified-Since` or `Last-Modified`
+    ///
+    /// An invalid date counts as absent, as recipients are told to do (RFC 9110 §13.1.3).
+    pub fn get_date(&self, name: &str) -> Option<HttpDate> {
+        self.get(name)?.parse().ok()
+    }
+
+    /// Set `name` to a single value, replacing any previous values in place
+    pub fn insert(&mut self, name: impl Into<HeaderName>, value: impl Into<String>) {
+        let name = name.into();
+
+        match self.entries.iter().position(|(k, _)| *k == name) {
+            Some(idx) => {
+                let mut seen_first = false;
+
+                self.entries[idx].1 = value.into();
+                self.entries.retain(|(k, _)| {
+                    let duplicate = *k == name && seen_first;
+
+                    seen_first |= *k == name;
+                    !duplicate
+                });
+            }
+            None => self.entries.push((name, value.into())),
+        }
+    }
+
+    /// Add a value to `name`, keeping the values already present
+    pub fn append(&mut self, name: impl Into<HeaderName>, value: impl Into<String>) {
+        self.entries.push((name.into(), value.into()));
+    }
+
+    /// Like [`HeaderMap::insert`], but refuses a field that could split the message,
+    /// which matters whenever the name or value comes from user input
+    pub fn try_insert(
+        &mut self,
+        name: impl Into<HeaderName>,
+        value: impl Into<String>,
+    ) -> Result<(), HeaderError> {
+        let (name, value) = (name.into(), value.into());
+
+        check_field(&name, &value)?;
+        self.insert(name, value);
+
+        Ok(())
+    }
+
+    /// Like [`HeaderMap::append`], but refuses a field that could split the message
+    pub fn try_append(
+        &mut self,
+        name: impl Into<HeaderName>,
+        value: impl Into<String>,
+    ) -> Result<(), HeaderError> {
+        let (name, value) = (name.into(), value.into());
+
+        check_field(&name, &value)?;
+        self.append(name, value);
+
+        Ok(())
+    }
+
+    /// Check that every field can be serialized as is
+    pub fn validate(&self) -> Result<(), HeaderError> {
+        self.entries
+            .iter()
+            .try_for_each(|(name, value)| check_field(name, value))
+    }
+
+    /// Remove every value of `name`, returning the first one
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let first = self
+            .entries
+            .iter()
+            .position(|(k, _)| k == name)
+            .map(|idx| self.entries.remove(idx).1);
+
+        self.entries.retain(|(k, _)| k != name);
+
+        first
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&HeaderName, &str)> {
+        self.entries.iter().map(|(k, v)| (k, v.as_str()))
+    }
+
+    /// Number of values, counting every value of a repeated name
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<K: Into<HeaderName>, V: Into<String>> FromIterator<(K, V)> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> HeaderMap {
+        let mut map = HeaderMap::new();
+
+        for (k, v) in iter {
+            map.append(k, v);
+        }
+
+        map
+    }
+}
+
+fn check_field(name: &HeaderName, value: &str) -> Result<(), HeaderError> {
+    if !name.is_valid() {
+        return Err(HeaderError::InvalidName(name.to_string()));
+    }
+
+    if !value.bytes().all(is_field_vchar) {
+        return Err(HeaderError::InvalidValue(name.to_string()));
+    }
+
+    Ok(())
+}
+
+/// `tchar` from RFC 9110 §5.6.2, the alphabet of methods and header names
+pub(crate) fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Bytes allowed in a field value: visible characters, spaces, tabs and obs-text
+/// (RFC 9110 §5.5); in particular no CR, LF or NUL
+pub(crate) fn is_field_vchar(b: u8) -> bool {
+    b == b'\t' || (b' '..=b'~').contains(&b) || b >= 0x80
+}
+
+#[cfg(test)]
+mod header_test {
+    use super::*;
+
+    #[test]
+    fn test_case_insensitive_lookup() {
+        let mut headers = HeaderMap::new();
+
+        headers.append("Content-Type", "text/html");
+
+        assert_eq!(headers.get("content-type"), Some("text/html"));
+        assert_eq!(headers.get("CONTENT-TYPE"), Some("text/html"));
+        assert!(headers.contains_key("Content-type"));
+        assert_eq!(HeaderName::from("HOST"), HeaderName::from("host"));
+    }
+
+    #[test]
+    fn test_multiple_values() {
+        let mut headers = HeaderMap::new();
+
+        headers.append("Set-Cookie", "a=1");
+        headers.append("Content-Type", "text/html");
+        headers.append("set-cookie", "b=2");
+
+        assert_eq!(headers.get("Set-Cookie"), Some("a=1"));
+        assert_eq!(
+            headers.get_all("SET-COOKIE").collect::<Vec<_>>(),
+            vec!["a=1", "b=2"]
+        );
+        assert_eq!(headers.len(), 3);
+
+        headers.insert("Set-Cookie", "c=3");
+
+        assert_eq!(
+            headers
+                .iter()
+                .map(|(k, v)| format!("{}: {}", k, v))
+                .collect::<Vec<_>>(),
+            vec!["Set-Cookie: c=3", "Content-Type: text/html"]
+        );
+        assert_eq!(headers.remove("set-cookie"), Some("c=3".into()));
+        assert_eq!(headers.remove("set-cookie"), None);
+        assert_eq!(headers.len(), 1);
+    }
+
+    #[test]
+    fn test_get_date() {
+        let mut headers = HeaderMap::new();
+
+        headers.append("If-Modified-Since", "Sat, 24 Sep 2022 03:15:53 GMT");
+        headers.append("Last-Modified", "last tuesday");
+
+        assert_eq!(
+            headers.get_date("if-modified-since").map(|d| d.to_string()),
+            Some("Sat, 24 Sep 2022 03:15:53 GMT".into())
+        );
+        assert_eq!(headers.get_date("Last-Modified"), None);
+        assert_eq!(headers.get_date("Date"), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut headers = HeaderMap::new();
+
+        assert!(headers.try_insert("X-Name", "caf\u{e9}\tok").is_ok());
+        assert_eq!(
+            headers.try_insert("X-Name", "a\r\nSet-Cookie: evil=1"),
+            Err(HeaderError::InvalidValue("X-Name".into()))
+        );
+        assert_eq!(
+            headers.try_append("X-Bad\r\nName", "a"),
+            Err(HeaderError::InvalidName("X-Bad\r\nName".into()))
+        );
+        assert_eq!(
+            headers.try_append("", "a"),
+            Err(HeaderError::InvalidName("".into()))
+        );
+        assert_eq!(headers.get("X-Name"), Some("caf\u{e9}\tok"));
+        assert_eq!(headers.len(), 1);
+        assert!(headers.validate().is_ok());
+
+        headers.append("X-Null", "a\0b");
+
+        assert_eq!(
+            headers.validate(),
+            Err(HeaderError::InvalidValue("X-Null".into()))
+        );
+    }
+}
@@ -1,3 +1,16 @@
 pub mod response;
 
 pub mod request;
+
+pub mod header;
+
+pub mod body;
+
+pub mod uri;
+
+pub mod mime;
+
+pub mod date;
+
+#[cfg(feature = "async")]
+pub mod codec;
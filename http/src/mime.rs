@@ -0,0 +1,39 @@
+use std::path::Path;
+
+/// Guess a `Content-Type` from the file extension
+///
+/// Unknown and missing extensions get `application/octet-stream`, so that browsers
+/// download such files rather than sniff them as html.
+pub fn from_path(path: impl AsRef<Path>) -> &'static str {
+    match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+        Some("html" | "htm") => "text/html",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        Some("webp") => "image/webp",
+        Some("pdf") => "application/pdf",
+        Some("woff2") => "font/woff2",
+        Some("wasm") => "application/wasm",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod mime_test {
+    use super::*;
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(from_path("style.css"), "text/css");
+        assert_eq!(from_path("/img/logo.JPEG.png"), "image/png");
+        assert_eq!(from_path("index.html"), "text/html");
+        assert_eq!(from_path("upload.bin"), "application/octet-stream");
+        assert_eq!(from_path("no_extension"), "application/octet-stream");
+    }
+}
@@ -1,36 +1,101 @@
-use regex::Regex;
-use std::collections::HashMap;
-use std::process;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::header::{is_field_vchar, is_tchar, HeaderMap};
+use crate::uri::{Query, UriPath};
 
 /// Http methods
 ///
 /// include
 /// - GET
+/// - HEAD
 /// - POST
-#[derive(Debug, PartialEq)]
+/// - PUT
+/// - DELETE
+/// - CONNECT
+/// - OPTIONS
+/// - TRACE
+/// - PATCH
+/// - any other token as an extension method
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Method {
     Get,
+    Head,
     Post,
-    Uninitialized,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    Extension(String),
+}
+
+impl Method {
+    /// Safe methods are read-only by contract (RFC 9110 §9.2.1)
+    pub fn is_safe(&self) -> bool {
+        matches!(
+            self,
+            Method::Get | Method::Head | Method::Options | Method::Trace
+        )
+    }
+
+    /// Repeating an idempotent request has the same effect as sending it once (RFC 9110 §9.2.2)
+    pub fn is_idempotent(&self) -> bool {
+        self.is_safe() || matches!(self, Method::Put | Method::Delete)
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Patch => "PATCH",
+            Method::Extension(method) => method,
+        }
+    }
 }
 
-impl From<&str> for Method {
-    fn from(str: &str) -> Method {
+impl FromStr for Method {
+    type Err = ParseError;
+
+    /// Methods are case-sensitive, so `get` is an extension method rather than `GET`
+    fn from_str(str: &str) -> Result<Method, ParseError> {
         match str {
-            "GET" => Method::Get,
-            "POST" => Method::Post,
-            _ => Method::Uninitialized,
+            "GET" => Ok(Method::Get),
+            "HEAD" => Ok(Method::Head),
+            "POST" => Ok(Method::Post),
+            "PUT" => Ok(Method::Put),
+            "DELETE" => Ok(Method::Delete),
+            "CONNECT" => Ok(Method::Connect),
+            "OPTIONS" => Ok(Method::Options),
+            "TRACE" => Ok(Method::Trace),
+            "PATCH" => Ok(Method::Patch),
+            s if !s.is_empty() && s.bytes().all(is_tchar) => Ok(Method::Extension(s.into())),
+            _ => Err(ParseError::InvalidMethod),
         }
     }
 }
 
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Http versions
 ///
 /// include
 /// - HTTP/1.0
 /// - HTTP/1.1
 /// - HTTP/2.0
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Version {
     V1_0,
     V1_1,
@@ -60,10 +125,109 @@ impl From<&Version> for String {
     }
 }
 
-/// Http rescourses
+/// Http rescourses, i.e. the request-target (RFC 9112 §3.2)
 #[derive(Debug, PartialEq)]
 pub enum Rescourse {
-    Path(String),
+    /// origin-form, e.g. `/orders?status=true`
+    Path { path: UriPath, query: Query },
+    /// absolute-form, e.g. `http://localhost:3000/orders?status=true`
+    Absolute {
+        scheme: String,
+        authority: String,
+        path: UriPath,
+        query: Query,
+    },
+    /// authority-form, only used by CONNECT, e.g. `localhost:3000`
+    Authority(String),
+    /// asterisk-form, only used by a server-wide OPTIONS
+    Asterisk,
+}
+
+impl Rescourse {
+    /// Parse a request-target, checking that its form is allowed for `method`
+    pub fn parse(method: &Method, target: &str) -> Result<Rescourse, ParseError> {
+        if !target.bytes().all(|b| b.is_ascii_graphic()) || target.contains('#') {
+            return Err(ParseError::BadTarget);
+        }
+
+        match (method, target) {
+            (Method::Connect, authority) => {
+                let (host, port) = authority.rsplit_once(':').ok_or(ParseError::BadTarget)?;
+
+                if host.is_empty()
+                    || authority.contains(['/', '?', '@'])
+                    || port.is_empty()
+                    || !port.bytes().all(|b| b.is_ascii_digit())
+                {
+                    return Err(ParseError::BadTarget);
+                }
+
+                Ok(Rescourse::Authority(authority.into()))
+            }
+            (Method::Options, "*") => Ok(Rescourse::Asterisk),
+            (_, t) if t.starts_with('/') => {
+                let (path, query) = split_query(t)?;
+
+                Ok(Rescourse::Path { path, query })
+            }
+            (_, t) => {
+                let (scheme, rest) = t.split_once("://").ok_or(ParseError::BadTarget)?;
+                let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+                let (authority, rest) = rest.split_at(authority_end);
+
+                if !is_scheme(scheme) || authority.is_empty() {
+                    return Err(ParseError::BadTarget);
+                }
+
+                let (path, query) = split_query(rest)?;
+
+                Ok(Rescourse::Absolute {
+                    scheme: scheme.to_ascii_lowercase(),
+                    authority: authority.into(),
+                    path,
+                    query,
+                })
+            }
+        }
+    }
+
+    /// Path of the target; `*` for asterisk-form and empty for authority-form
+    pub fn path(&self) -> &str {
+        match self {
+            Rescourse::Path { path, .. } | Rescourse::Absolute { path, .. } => path.as_str(),
+            Rescourse::Authority(_) => "",
+            Rescourse::Asterisk => "*",
+        }
+    }
+
+    /// Query parameters, which only origin-form and absolute-form targets carry
+    pub fn query(&self) -> Option<&Query> {
+        match self {
+            Rescourse::Path { query, .. } | Rescourse::Absolute { query, .. } => Some(query),
+            _ => None,
+        }
+    }
+}
+
+/// Split a target into its normalized path and query; an empty path means `/`
+fn split_query(target: &str) -> Result<(UriPath, Query), ParseError> {
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Query::parse(query)?),
+        None => (target, Query::default()),
+    };
+
+    match path {
+        "" => Ok((UriPath::default(), query)),
+        path => Ok((UriPath::parse(path)?, query)),
+    }
+}
+
+/// `scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )` (RFC 3986 §3.1)
+fn is_scheme(scheme: &str) -> bool {
+    let mut bytes = scheme.bytes();
+
+    matches!(bytes.next(), Some(b) if b.is_ascii_alphabetic())
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b"+-.".contains(&b))
 }
 
 #[derive(Debug)]
@@ -71,88 +235,823 @@ pub struct HttpRequest {
     pub mothod: Method,
     pub version: Version,
     pub resource: Rescourse,
-    pub headers: HashMap<String, String>,
-    pub body: Option<String>,
+    pub headers: HeaderMap,
+    pub body: Option<Vec<u8>>,
+    /// Trailer fields sent after a chunked body
+    pub trailers: HeaderMap,
 }
 
-impl From<String> for HttpRequest {
-    fn from(str: String) -> HttpRequest {
-        let mut parsed_method = Method::Uninitialized;
-        let mut parsed_recourse = Rescourse::Path("".into());
-        let mut parsed_version = Version::Uninitialized;
-        let mut parsed_headers = HashMap::new();
-        let mut parsed_body = None;
+impl HttpRequest {
+    /// Whether the client expects the connection to stay open after the response
+    ///
+    /// HTTP/1.1 connections persist unless the client sends `Connection: close`,
+    /// HTTP/1.0 ones only when it sends `Connection: keep-alive` (RFC 9112 §9.3).
+    pub fn keep_alive(&self) -> bool {
+        match self.version {
+            Version::V1_1 => !self.headers.contains_token("Connection", "close"),
+            Version::V1_0 => self.headers.contains_token("Connection", "keep-alive"),
+            _ => false,
+        }
+    }
+}
 
-        println!("{:#?}", str);
+impl TryFrom<&[u8]> for HttpRequest {
+    type Error = ParseError;
 
-        for line in str.lines() {
-            match line {
-                l if Regex::new(r"(GET|POST)\s/[0-9a-zA-z/.]*\sHTTP/(1\.1|1\.2|2\.0)")
-                    .unwrap()
-                    .is_match(l) =>
-                {
-                    let (method, recourse, version) = process_request_line(l);
+    /// Parse a request that has been received in full
+    fn try_from(bytes: &[u8]) -> Result<HttpRequest, ParseError> {
+        RequestParser::new().finish(bytes)
+    }
+}
+
+impl TryFrom<String> for HttpRequest {
+    type Error = ParseError;
+
+    fn try_from(str: String) -> Result<HttpRequest, ParseError> {
+        HttpRequest::try_from(str.as_bytes())
+    }
+}
+
+/// Longest request line accepted before answering `414 URI Too Long`
+pub const MAX_REQUEST_LINE_LEN: usize = 8 * 1024;
+
+/// Largest request head accepted before answering `431 Request Header Fields Too Large`
+pub const MAX_HEAD_LEN: usize = 64 * 1024;
+
+/// Longest single header line accepted before answering `431 Request Header Fields Too Large`
+pub const MAX_HEADER_LINE_LEN: usize = 8 * 1024;
+
+/// Most header fields accepted before answering `431 Request Header Fields Too Large`
+pub const MAX_HEADER_COUNT: usize = 100;
+
+/// Largest request body accepted before answering `413 Payload Too Large`
+pub const MAX_BODY_LEN: usize = 8 * 1024 * 1024;
+
+/// Limits and strictness of a [`RequestParser`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParserConfig {
+    /// Reject everything RFC 9112 merely tolerates: bare LF line endings, whitespace
+    /// before the colon of a header and control characters in field values
+    pub strict: bool,
+    pub max_request_line: usize,
+    pub max_header_line: usize,
+    pub max_headers: usize,
+    /// Total size of the request line and all header lines
+    pub max_head: usize,
+    pub max_body: usize,
+}
+
+impl Default for ParserConfig {
+    fn default() -> ParserConfig {
+        ParserConfig {
+            strict: false,
+            max_request_line: MAX_REQUEST_LINE_LEN,
+            max_header_line: MAX_HEADER_LINE_LEN,
+            max_headers: MAX_HEADER_COUNT,
+            max_head: MAX_HEAD_LEN,
+            max_body: MAX_BODY_LEN,
+        }
+    }
+}
+
+impl ParserConfig {
+    /// The default limits with strict validation, meant for untrusted traffic
+    pub fn strict() -> ParserConfig {
+        ParserConfig {
+            strict: true,
+            ..ParserConfig::default()
+        }
+    }
+
+    /// Most bytes a request can take up, from the empty lines before it to the end of
+    /// its body, before a [`RequestParser`] either has it complete or rejects it
+    ///
+    /// A connection never needs to buffer more than this for one request.
+    pub fn max_request_len(&self) -> usize {
+        // Framing up to the limit, the data of the chunk being read, trailers and a line
+        // not finished yet
+        let chunked = max_encoded_len(self.max_body)
+            .saturating_add(self.max_body)
+            .saturating_add(self.max_head)
+            .saturating_add(MAX_CHUNK_LINE_LEN);
+
+        self.max_head.saturating_add(self.max_body.max(chunked))
+    }
+}
+
+/// Errors produced while parsing a request
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    BadRequestLine,
+    BadHeader,
+    InvalidMethod,
+    UnsupportedVersion,
+    UriTooLong,
+    HeadersTooLarge,
+    BadTarget,
+    BodyTooLarge,
+    UnexpectedEof,
+    BadChunk,
+    UnsupportedTransferCoding,
+    AmbiguousFraming,
+    MissingHost,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::BadRequestLine => write!(f, "malformed request line"),
+            ParseError::BadHeader => write!(f, "malformed header field"),
+            ParseError::InvalidMethod => write!(f, "invalid request method"),
+            ParseError::UnsupportedVersion => write!(f, "unsupported http version"),
+            ParseError::UriTooLong => write!(f, "request line too long"),
+            ParseError::HeadersTooLarge => write!(f, "request head too large"),
+            ParseError::BadTarget => write!(f, "invalid request target"),
+            ParseError::BodyTooLarge => write!(f, "request body too large"),
+            ParseError::UnexpectedEof => write!(f, "connection closed mid-request"),
+            ParseError::BadChunk => write!(f, "malformed chunked body"),
+            ParseError::UnsupportedTransferCoding => write!(f, "unsupported transfer coding"),
+            ParseError::AmbiguousFraming => write!(f, "ambiguous message body length"),
+            ParseError::MissingHost => write!(f, "missing host header"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Result of feeding bytes to a [`RequestParser`]
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum ParseStatus {
+    /// The request has not been fully received yet
+    Incomplete,
+    /// A request was parsed from the first `usize` bytes of the buffer
+    Complete(HttpRequest, usize),
+}
+
+/// Incremental request parser
+///
+/// The caller keeps appending received bytes to one buffer and passes the whole buffer
+/// on every call. The parser remembers how far it has already scanned, and keeps a parsed
+/// head around while its body arrives, so a request that trickles in over many reads is
+/// only parsed once. After `Complete` the consumed bytes should be drained from the buffer
+/// before parsing the next request.
+#[derive(Debug, Default)]
+pub struct RequestParser {
+    state: ParseState,
+    config: ParserConfig,
+}
+
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+enum ParseState {
+    /// Looking for the end of the head, resuming the search at `scanned`
+    Head { scanned: usize },
+    /// The head takes `head_len` bytes and is followed by a body framed by `framing`
+    Body {
+        request: HttpRequest,
+        head_len: usize,
+        framing: Framing,
+    },
+}
+
+impl Default for ParseState {
+    fn default() -> ParseState {
+        ParseState::Head { scanned: 0 }
+    }
+}
+
+/// How the end of a request body is found (RFC 9112 §6.3)
+#[derive(Debug)]
+enum Framing {
+    Length(usize),
+    Chunked(ChunkedDecoder),
+}
+
+impl RequestParser {
+    pub fn new() -> RequestParser {
+        RequestParser::default()
+    }
+
+    pub fn with_config(config: ParserConfig) -> RequestParser {
+        RequestParser {
+            state: ParseState::default(),
+            config,
+        }
+    }
+
+    pub fn parse(&mut self, buf: &[u8]) -> Result<ParseStatus, ParseError> {
+        let status = self.advance(buf);
+
+        if status.is_err() {
+            self.state = ParseState::default();
+        }
 
-                    parsed_method = method;
-                    parsed_recourse = recourse;
-                    parsed_version = version;
+        status
+    }
+
+    /// Finish parsing once the peer has stopped sending
+    ///
+    /// A head missing its final empty line is accepted, but a body shorter than its
+    /// framing promised is not.
+    pub fn finish(&mut self, buf: &[u8]) -> Result<HttpRequest, ParseError> {
+        if let ParseStatus::Complete(request, _) = self.parse(buf)? {
+            return Ok(request);
+        }
+
+        match std::mem::take(&mut self.state) {
+            ParseState::Head { .. } => {
+                let start = skip_empty_lines(buf, &self.config)?;
+                let request = parse_head(&buf[start..], &self.config)?;
+                let framing = framing(&request, &self.config)?;
+
+                check_host(&request)?;
+
+                match framing {
+                    Framing::Length(0) => Ok(request),
+                    _ => Err(ParseError::UnexpectedEof),
                 }
-                l if Regex::new(r"[a-zA-Z-]*:\s[\d\D]+").unwrap().is_match(l) => {
-                    let dot_idx = l.find(':').unwrap();
-                    let header_key = l[..dot_idx].trim().to_string();
-                    let header_value = l[dot_idx + 1..].trim().to_string();
+            }
+            ParseState::Body { .. } => Err(ParseError::UnexpectedEof),
+        }
+    }
 
-                    parsed_headers.entry(header_key).or_insert(header_value);
+    fn advance(&mut self, buf: &[u8]) -> Result<ParseStatus, ParseError> {
+        if let ParseState::Head { scanned } = self.state {
+            let start = skip_empty_lines(buf, &self.config)?;
+            let head_end = match find_head_end(buf, scanned.clamp(start, buf.len())) {
+                Some(head_end) => head_end,
+                None => {
+                    check_head_len(&buf[start..], start, &self.config)?;
+
+                    // A terminator may straddle the end of the buffer, so back off a little.
+                    self.state = ParseState::Head {
+                        scanned: buf.len().saturating_sub(3),
+                    };
+
+                    return Ok(ParseStatus::Incomplete);
                 }
+            };
+
+            check_head_len(&buf[start..head_end], start, &self.config)?;
+
+            let request = parse_head(&buf[start..head_end], &self.config)?;
+            let framing = framing(&request, &self.config)?;
+
+            check_host(&request)?;
+
+            self.state = ParseState::Body {
+                request,
+                head_len: head_end,
+                framing,
+            };
+        }
+
+        let ParseState::Body {
+            request,
+            head_len,
+            framing,
+        } = &mut self.state
+        else {
+            unreachable!("the head is always parsed above")
+        };
+        let head_len = *head_len;
+
+        let consumed = match framing {
+            Framing::Length(body_len) if buf.len() < head_len + *body_len => None,
+            Framing::Length(body_len) => {
+                if *body_len > 0 {
+                    request.body = Some(buf[head_len..head_len + *body_len].to_vec());
+                }
+
+                Some(head_len + *body_len)
+            }
+            Framing::Chunked(decoder) => match decoder.decode(&buf[head_len..])? {
+                Some(body_len) => {
+                    let (body, trailers) = decoder.take();
+
+                    request.body = Some(body).filter(|b| !b.is_empty());
+                    request.trailers = trailers;
+
+                    Some(head_len + body_len)
+                }
+                None => None,
+            },
+        };
+
+        match consumed {
+            Some(consumed) => match std::mem::take(&mut self.state) {
+                ParseState::Body { request, .. } => Ok(ParseStatus::Complete(request, consumed)),
+                ParseState::Head { .. } => unreachable!(),
+            },
+            None => Ok(ParseStatus::Incomplete),
+        }
+    }
+}
+
+/// Work out how the request body is framed; requests with neither
+/// `Transfer-Encoding` nor `Content-Length` have no body
+///
+/// Anything a proxy in front of us could read differently is rejected (RFC 9112 §6.3):
+/// both fields at once, `Transfer-Encoding` on an HTTP/1.0 request or without chunked
+/// as its final coding, and `Content-Length` values that disagree.
+fn framing(request: &HttpRequest, config: &ParserConfig) -> Result<Framing, ParseError> {
+    let headers = &request.headers;
+
+    if headers.contains_key("Transfer-Encoding") {
+        if headers.contains_key("Content-Length") || request.version == Version::V1_0 {
+            return Err(ParseError::AmbiguousFraming);
+        }
+
+        let codings: Vec<&str> = headers
+            .get_all("Transfer-Encoding")
+            .flat_map(|v| v.split(','))
+            .map(|c| c.trim())
+            .filter(|c| !c.is_empty())
+            .collect();
+
+        return match codings[..] {
+            [.., last] if last.eq_ignore_ascii_case("chunked") => {
+                if codings.len() > 1 {
+                    // Only chunked itself is decoded, anything layered under it is not.
+                    return Err(ParseError::UnsupportedTransferCoding);
+                }
+
+                Ok(Framing::Chunked(ChunkedDecoder::with_config(config)))
+            }
+            // Without chunked last the body can only end when the connection does.
+            _ => Err(ParseError::AmbiguousFraming),
+        };
+    }
+
+    Ok(Framing::Length(content_length(headers, config.max_body)?))
+}
+
+/// HTTP/1.1 requests have to name exactly one host (RFC 9112 §3.2), in lenient mode too
+fn check_host(request: &HttpRequest) -> Result<(), ParseError> {
+    if request.version != Version::V1_1 {
+        return Ok(());
+    }
+
+    match request.headers.get_all("Host").count() {
+        0 => Err(ParseError::MissingHost),
+        1 => Ok(()),
+        _ => Err(ParseError::BadHeader),
+    }
+}
+
+/// Length of the request body from `Content-Length`
+///
+/// Repeated fields and lists like `5, 5` are accepted as long as every value is the
+/// same (RFC 9110 §8.6).
+fn content_length(headers: &HeaderMap, max_body: usize) -> Result<usize, ParseError> {
+    let mut values = headers
+        .get_all("Content-Length")
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim());
+    let length = match values.next() {
+        None => return Ok(0),
+        Some(v) if !v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()) => v,
+        Some(_) => return Err(ParseError::BadHeader),
+    };
+
+    if values.any(|v| v != length) {
+        return Err(ParseError::AmbiguousFraming);
+    }
+
+    match length.parse::<usize>() {
+        Ok(length) if length <= max_body => Ok(length),
+        _ => Err(ParseError::BodyTooLarge),
+    }
+}
 
-                l => {
-                    parsed_body = if l.len() > 0 {
-                        Some(l.to_string())
-                    } else {
-                        None
+/// Longest chunk-size line, extensions included, before the chunk is rejected
+const MAX_CHUNK_LINE_LEN: usize = 4 * 1024;
+
+/// Most bytes a chunked body decoding to at most `max_len` bytes may take on the wire,
+/// trailers aside
+///
+/// Chunk framing may at most double the body, plus a last chunk-size line. Sending a
+/// body in tiny chunks, or padding chunk-size lines with extensions, gets it rejected
+/// rather than buffered without bound.
+fn max_encoded_len(max_len: usize) -> usize {
+    max_len.saturating_mul(2).saturating_add(MAX_CHUNK_LINE_LEN)
+}
+
+/// Decoder for `Transfer-Encoding: chunked` bodies (RFC 9112 §7.1)
+///
+/// Like [`RequestParser`] it is handed every byte of the body received so far on each
+/// call and picks up where it stopped. Chunk extensions are validated and dropped, and
+/// trailer fields are collected separately from the body.
+#[derive(Debug)]
+pub struct ChunkedDecoder {
+    max_len: usize,
+    max_trailers_len: usize,
+    strict: bool,
+    pos: usize,
+    state: ChunkState,
+    body: Vec<u8>,
+    trailers: HeaderMap,
+    trailers_len: usize,
+}
+
+#[derive(Debug, PartialEq)]
+enum ChunkState {
+    Size,
+    Data(usize),
+    DataEnd,
+    Trailers,
+}
+
+impl ChunkedDecoder {
+    /// Create a decoder that rejects bodies decoding to more than `max_len` bytes,
+    /// and bare LF line endings or malformed trailers when `strict`
+    pub fn new(max_len: usize, strict: bool) -> ChunkedDecoder {
+        ChunkedDecoder {
+            max_len,
+            max_trailers_len: MAX_HEAD_LEN,
+            strict,
+            pos: 0,
+            state: ChunkState::Size,
+            body: Vec::new(),
+            trailers: HeaderMap::new(),
+            trailers_len: 0,
+        }
+    }
+
+    /// Create a decoder with the body limit, strictness and, for trailers, the head limit
+    /// of a [`RequestParser`]
+    pub fn with_config(config: &ParserConfig) -> ChunkedDecoder {
+        ChunkedDecoder {
+            max_trailers_len: config.max_head,
+            ..ChunkedDecoder::new(config.max_body, config.strict)
+        }
+    }
+
+    /// Decode as far as `buf` allows, returning the encoded length once the
+    /// last chunk and trailers have been read
+    pub fn decode(&mut self, buf: &[u8]) -> Result<Option<usize>, ParseError> {
+        loop {
+            match self.state {
+                ChunkState::Data(remaining) => {
+                    let available = remaining.min(buf.len() - self.pos);
+
+                    self.body
+                        .extend_from_slice(&buf[self.pos..self.pos + available]);
+                    self.pos += available;
+
+                    if available < remaining {
+                        self.state = ChunkState::Data(remaining - available);
+                        return Ok(None);
+                    }
+
+                    self.state = ChunkState::DataEnd;
+                }
+                _ => {
+                    let line = match self.next_line(buf)? {
+                        Some(line) => line,
+                        None => return Ok(None),
                     };
+
+                    if self.state != ChunkState::Trailers
+                        && self.pos > max_encoded_len(self.max_len)
+                    {
+                        return Err(ParseError::BodyTooLarge);
+                    }
+
+                    match self.state {
+                        ChunkState::Size => {
+                            let size = parse_chunk_size(line)?;
+
+                            // Written so that a huge size can't overflow past the check
+                            if size > self.max_len - self.body.len() {
+                                return Err(ParseError::BodyTooLarge);
+                            }
+
+                            self.state = match size {
+                                0 => ChunkState::Trailers,
+                                size => ChunkState::Data(size),
+                            };
+                        }
+                        ChunkState::DataEnd if line.is_empty() => self.state = ChunkState::Size,
+                        ChunkState::DataEnd => return Err(ParseError::BadChunk),
+                        ChunkState::Trailers if line.is_empty() => return Ok(Some(self.pos)),
+                        ChunkState::Trailers => {
+                            // Counting the line terminator keeps empty-ish lines in check
+                            self.trailers_len += line.len() + 2;
+
+                            if self.trailers_len > self.max_trailers_len {
+                                return Err(ParseError::HeadersTooLarge);
+                            }
+
+                            let (key, value) = process_header_line(line, self.strict)?;
+
+                            self.trailers.append(key, value);
+                        }
+                        ChunkState::Data(_) => unreachable!(),
+                    }
                 }
             }
         }
+    }
+
+    /// Take the decoded body and trailer fields
+    pub fn take(&mut self) -> (Vec<u8>, HeaderMap) {
+        (
+            std::mem::take(&mut self.body),
+            std::mem::take(&mut self.trailers),
+        )
+    }
+
+    /// Next line without its terminator, advancing past it
+    fn next_line<'a>(&mut self, buf: &'a [u8]) -> Result<Option<&'a [u8]>, ParseError> {
+        let rest = &buf[self.pos..];
+
+        match rest.iter().position(|b| *b == b'\n') {
+            Some(lf) if lf <= MAX_CHUNK_LINE_LEN => {
+                let line = match rest[..lf].strip_suffix(b"\r") {
+                    Some(line) => line,
+                    None if self.strict => return Err(ParseError::BadChunk),
+                    None => &rest[..lf],
+                };
+
+                self.pos += lf + 1;
+
+                Ok(Some(line))
+            }
+            None if rest.len() <= MAX_CHUNK_LINE_LEN => Ok(None),
+            _ => Err(ParseError::BadChunk),
+        }
+    }
+}
 
-        HttpRequest {
-            mothod: parsed_method,
-            version: parsed_version,
-            resource: parsed_recourse,
-            headers: parsed_headers,
-            body: parsed_body,
+/// `chunk-size [ chunk-ext ]`, where `chunk-ext = *( BWS ";" BWS ext-name [ BWS "=" BWS ext-val ] )`
+fn parse_chunk_size(line: &[u8]) -> Result<usize, ParseError> {
+    let line = std::str::from_utf8(line).map_err(|_| ParseError::BadChunk)?;
+    let (size, extensions) = match line.split_once(';') {
+        Some((size, extensions)) => (size.trim_end_matches([' ', '\t']), Some(extensions)),
+        None => (line, None),
+    };
+
+    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ParseError::BadChunk);
+    }
+
+    for extension in extensions.into_iter().flat_map(|e| e.split(';')) {
+        let (name, value) = match extension.split_once('=') {
+            Some((name, value)) => (name, Some(value.trim_matches([' ', '\t']))),
+            None => (extension, None),
+        };
+        let name = name.trim_matches([' ', '\t']);
+        let valid_value = match value {
+            None => true,
+            Some(v) if v.len() >= 2 && v.starts_with('"') && v.ends_with('"') => true,
+            Some(v) => !v.is_empty() && v.bytes().all(is_tchar),
+        };
+
+        if name.is_empty() || !name.bytes().all(is_tchar) || !valid_value {
+            return Err(ParseError::BadChunk);
         }
     }
+
+    usize::from_str_radix(size, 16).map_err(|_| ParseError::BadChunk)
 }
 
-fn process_request_line(line: &str) -> (Method, Rescourse, Version) {
-    let request_line: Vec<&str> = line.split(" ").collect();
+/// Servers should ignore empty lines received before the request line (RFC 9112 §2.2),
+/// but only as many as would fit in one
+fn skip_empty_lines(buf: &[u8], config: &ParserConfig) -> Result<usize, ParseError> {
+    let scan = &buf[..buf.len().min(config.max_request_line + 1)];
+    let start = scan
+        .iter()
+        .position(|b| *b != b'\r' && *b != b'\n')
+        .unwrap_or(scan.len());
 
-    if request_line.len() != 3 {
-        eprintln!("Process request line error: {:?}", request_line);
-        process::exit(1);
+    match start > config.max_request_line {
+        true => Err(ParseError::BadRequestLine),
+        false => Ok(start),
     }
+}
+
+/// Find the end of the request head, i.e. the index just past the empty line
+fn find_head_end(buf: &[u8], from: usize) -> Option<usize> {
+    let mut idx = from;
+
+    while let Some(offset) = buf[idx..].iter().position(|b| *b == b'\n') {
+        let lf = idx + offset;
+
+        match &buf[lf + 1..] {
+            [b'\n', ..] => return Some(lf + 2),
+            [b'\r', b'\n', ..] => return Some(lf + 3),
+            _ => idx = lf + 1,
+        }
+    }
+
+    None
+}
 
-    (
-        request_line[0].into(),
-        Rescourse::Path(request_line[1].to_string()),
-        request_line[2].into(),
+/// Enforce the size limits on a (possibly partial) request head, which `skipped` empty
+/// lines came before
+fn check_head_len(head: &[u8], skipped: usize, config: &ParserConfig) -> Result<(), ParseError> {
+    let request_line_len = head.iter().position(|b| *b == b'\n').unwrap_or(head.len());
+    let last_line_len = head.len()
+        - head
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(0, |lf| lf + 1);
+
+    if request_line_len > config.max_request_line {
+        Err(ParseError::UriTooLong)
+    } else if skipped + head.len() > config.max_head
+        || (request_line_len < head.len() && last_line_len > config.max_header_line)
+    {
+        Err(ParseError::HeadersTooLarge)
+    } else {
+        Ok(())
+    }
+}
+
+/// Strict mode only accepts CRLF, never a bare LF or a CR on its own
+fn check_line_endings(head: &[u8]) -> Result<(), ParseError> {
+    let bad_ending = head.iter().enumerate().position(|(idx, b)| match b {
+        b'\n' => idx == 0 || head[idx - 1] != b'\r',
+        b'\r' => head.get(idx + 1) != Some(&b'\n'),
+        _ => false,
+    });
+    let request_line_len = head.iter().position(|b| *b == b'\n').unwrap_or(head.len());
+
+    match bad_ending {
+        Some(idx) if idx <= request_line_len => Err(ParseError::BadRequestLine),
+        Some(_) => Err(ParseError::BadHeader),
+        None => Ok(()),
+    }
+}
+
+/// Split a buffer into lines, accepting both CRLF and bare LF terminators
+fn lines(buf: &[u8]) -> impl Iterator<Item = &[u8]> {
+    buf.split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+fn parse_head(head: &[u8], config: &ParserConfig) -> Result<HttpRequest, ParseError> {
+    if config.strict {
+        check_line_endings(head)?;
+    }
+
+    let mut lines = lines(head);
+    let (method, resource, version) =
+        process_request_line(lines.next().ok_or(ParseError::BadRequestLine)?)?;
+    let mut headers = HeaderMap::new();
+
+    for line in lines.filter(|l| !l.is_empty()) {
+        if line.len() > config.max_header_line || headers.len() == config.max_headers {
+            return Err(ParseError::HeadersTooLarge);
+        }
+
+        let (key, value) = process_header_line(line, config.strict)?;
+
+        headers.append(key, value);
+    }
+
+    Ok(HttpRequest {
+        mothod: method,
+        version,
+        resource,
+        headers,
+        body: None,
+        trailers: HeaderMap::new(),
+    })
+}
+
+fn process_request_line(line: &[u8]) -> Result<(Method, Rescourse, Version), ParseError> {
+    let line = std::str::from_utf8(line).map_err(|_| ParseError::BadRequestLine)?;
+    let request_line: Vec<&str> = line.split(' ').collect();
+
+    let (method, path, version) = match request_line[..] {
+        [method, path, version] if !path.is_empty() => (method, path, version),
+        _ => return Err(ParseError::BadRequestLine),
+    };
+
+    let method: Method = method.parse()?;
+
+    if !is_version_syntax(version) {
+        return Err(ParseError::BadRequestLine);
+    }
+
+    let version = match Version::from(version) {
+        // HTTP/2 has no text request line, so `HTTP/2.0` here is as unknown as `HTTP/1.2`
+        Version::Uninitialized | Version::V2_0 => return Err(ParseError::UnsupportedVersion),
+        version => version,
+    };
+    let resource = Rescourse::parse(&method, path)?;
+
+    Ok((method, resource, version))
+}
+
+/// `HTTP-version = HTTP-name "/" DIGIT "." DIGIT` (RFC 9112 §2.3)
+fn is_version_syntax(version: &str) -> bool {
+    matches!(
+        version.as_bytes(),
+        [b'H', b'T', b'T', b'P', b'/', major, b'.', minor]
+            if major.is_ascii_digit() && minor.is_ascii_digit()
     )
 }
 
+/// Strip optional whitespace, i.e. spaces and tabs, from both ends
+fn trim_ows(bytes: &[u8]) -> &[u8] {
+    let is_ows = |b: &u8| *b == b' ' || *b == b'\t';
+    let start = bytes.iter().position(|b| !is_ows(b)).unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !is_ows(b))
+        .map_or(start, |idx| idx + 1);
+
+    &bytes[start..end]
+}
+
+/// `field-line = field-name ":" OWS field-value OWS` (RFC 9112 §5)
+///
+/// Indented lines are always refused: a folded line (obs-fold) or whitespace before the
+/// first field would hide the field from a proxy that unfolds or drops it (RFC 9112
+/// §2.2, §5.2). Lenient parsing otherwise only refuses the field-value characters that
+/// could split a field, strict parsing checks the full grammar.
+fn process_header_line(line: &[u8], strict: bool) -> Result<(String, String), ParseError> {
+    let colon = line
+        .iter()
+        .position(|b| *b == b':')
+        .ok_or(ParseError::BadHeader)?;
+    // `Host : a` is read as a different field by some proxies, so whitespace around the
+    // name is never trimmed (RFC 9112 §5.1) and fails the token check below.
+    let key = &line[..colon];
+    let value = trim_ows(&line[colon + 1..]);
+
+    if key.is_empty() || !key.iter().copied().all(is_tchar) {
+        return Err(ParseError::BadHeader);
+    }
+
+    let valid_value = match strict {
+        true => value.iter().copied().all(is_field_vchar),
+        false => !value.iter().any(|b| matches!(b, b'\0' | b'\r' | b'\n')),
+    };
+
+    if !valid_value {
+        return Err(ParseError::BadHeader);
+    }
+
+    let key = std::str::from_utf8(key).map_err(|_| ParseError::BadHeader)?;
+    let value = std::str::from_utf8(value).map_err(|_| ParseError::BadHeader)?;
+
+    Ok((key.to_string(), value.to_string()))
+}
+
 #[cfg(test)]
 mod request_test {
     use super::*;
 
     #[test]
     fn test_method_into() {
-        let method_get: Method = "GET".into();
-        let method_post: Method = "POST".into();
+        let method_get: Method = "GET".parse().unwrap();
+        let method_post: Method = "POST".parse().unwrap();
 
         assert_eq!(method_get, Method::Get);
         assert_eq!(method_post, Method::Post);
     }
 
+    #[test]
+    fn test_method_from_str() {
+        let methods = [
+            Method::Get,
+            Method::Head,
+            Method::Post,
+            Method::Put,
+            Method::Delete,
+            Method::Connect,
+            Method::Options,
+            Method::Trace,
+            Method::Patch,
+            Method::Extension("PROPFIND".into()),
+        ];
+
+        for method in methods {
+            assert_eq!(method.to_string().parse::<Method>(), Ok(method));
+        }
+
+        assert_eq!("get".parse(), Ok(Method::Extension("get".into())));
+        assert_eq!("".parse::<Method>(), Err(ParseError::InvalidMethod));
+        assert_eq!("GE T".parse::<Method>(), Err(ParseError::InvalidMethod));
+    }
+
+    #[test]
+    fn test_method_classification() {
+        assert!(Method::Get.is_safe());
+        assert!(Method::Head.is_idempotent());
+        assert!(!Method::Put.is_safe());
+        assert!(Method::Put.is_idempotent());
+        assert!(Method::Delete.is_idempotent());
+        assert!(!Method::Post.is_idempotent());
+        assert!(!Method::Patch.is_idempotent());
+        assert!(!Method::Extension("PROPFIND".into()).is_safe());
+    }
+
     #[test]
     fn test_version_into() {
         let version_1_0: Version = "HTTP/1.0".into();
@@ -166,41 +1065,599 @@ mod request_test {
 
     #[test]
     fn test_parse_request_line() {
-        let (method, resource, version) = process_request_line("GET / HTTP/1.0");
+        let (method, resource, version) = process_request_line(b"GET / HTTP/1.0").unwrap();
 
         assert_eq!(
-            (method, resource, version),
-            (Method::Get, Rescourse::Path("/".into()), Version::V1_0)
+            (method, resource.path(), version),
+            (Method::Get, "/", Version::V1_0)
         );
 
-        let (method, resource, version) = process_request_line("POST /foo HTTP/1.1");
+        let (method, resource, version) = process_request_line(b"POST /foo HTTP/1.1").unwrap();
 
         assert_eq!(
-            (method, resource, version),
-            (Method::Post, Rescourse::Path("/foo".into()), Version::V1_1)
+            (method, resource.path(), version),
+            (Method::Post, "/foo", Version::V1_1)
         );
     }
 
     #[test]
     fn test_parse_request() {
-        let request: String = r#"GET / HTTP/1.1
-        Accept-Encoding: gzip, deflate, br
-        Accept-Language: zh-CN,zh;q=0.9
-        Cache-Control: max-age=0"#
+        let request: String = "GET / HTTP/1.1
+Host: localhost
+Accept-Encoding: gzip, deflate, br
+Accept-Language: zh-CN,zh;q=0.9
+Cache-Control: max-age=0"
             .into();
-        let parsed_request: HttpRequest = request.into();
-        let mut test_headers = HashMap::new();
+        let parsed_request: HttpRequest = request.try_into().unwrap();
+        let mut test_headers = HeaderMap::new();
 
-        test_headers.insert("Accept-Encoding".into(), "gzip, deflate, br".into());
-        test_headers.insert("Accept-Language".into(), "zh-CN,zh;q=0.9".into());
-        test_headers.insert("Cache-Control".into(), "max-age=0".into());
+        test_headers.insert("Host", "localhost");
+        test_headers.insert("Accept-Encoding", "gzip, deflate, br");
+        test_headers.insert("Accept-Language", "zh-CN,zh;q=0.9");
+        test_headers.insert("Cache-Control", "max-age=0");
 
         println!("{:#?}", parsed_request);
 
         assert_eq!(parsed_request.mothod, Method::Get);
-        assert_eq!(parsed_request.resource, Rescourse::Path("/".into()));
+        assert_eq!(parsed_request.resource.path(), "/");
         assert_eq!(parsed_request.version, Version::V1_1);
         assert_eq!(parsed_request.headers, test_headers);
         assert_eq!(parsed_request.body, None);
     }
+
+    #[test]
+    fn test_parse_incrementally() {
+        let raw = b"GET /index.html HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n";
+        let mut parser = RequestParser::new();
+
+        for end in 1..raw.len() {
+            assert!(matches!(
+                parser.parse(&raw[..end]),
+                Ok(ParseStatus::Incomplete)
+            ));
+        }
+
+        match parser.parse(raw) {
+            Ok(ParseStatus::Complete(request, consumed)) => {
+                assert_eq!(consumed, raw.len());
+                assert_eq!(request.mothod, Method::Get);
+                assert_eq!(request.resource.path(), "/index.html");
+                assert_eq!(request.headers.get("host"), Some("localhost"));
+            }
+            other => panic!("unexpected parse result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_pipelined() {
+        let raw = b"\r\nGET /a HTTP/1.1\r\nHost: a\r\n\r\nGET /b HTTP/1.1\r\nHost: a\r\n\r\n";
+        let mut parser = RequestParser::new();
+
+        let consumed = match parser.parse(raw) {
+            Ok(ParseStatus::Complete(request, consumed)) => {
+                assert_eq!(request.resource.path(), "/a");
+                consumed
+            }
+            other => panic!("unexpected parse result: {:?}", other),
+        };
+
+        match parser.parse(&raw[consumed..]) {
+            Ok(ParseStatus::Complete(request, consumed)) => {
+                assert_eq!(request.resource.path(), "/b");
+                assert_eq!(consumed, 28);
+            }
+            other => panic!("unexpected parse result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_bytes() {
+        let mut parser = RequestParser::new();
+
+        assert_eq!(
+            parser.parse(b"GET /\xff HTTP/1.1\r\n\r\n").unwrap_err(),
+            ParseError::BadRequestLine
+        );
+        assert_eq!(
+            parser
+                .parse(b"GET / HTTP/1.1\r\nX-Foo: \xfe\r\n\r\n")
+                .unwrap_err(),
+            ParseError::BadHeader
+        );
+        assert_eq!(
+            parser
+                .parse(b"GET / HTTP/1.1\r\nno colon here\r\n\r\n")
+                .unwrap_err(),
+            ParseError::BadHeader
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases: [(&[u8], ParseError); 7] = [
+            (b"GET /\r\n\r\n", ParseError::BadRequestLine),
+            (b"GET / HTTP/1.1\r\n: empty\r\n\r\n", ParseError::BadHeader),
+            (b"G(T / HTTP/1.1\r\n\r\n", ParseError::InvalidMethod),
+            (b"GET / HTTP/1.2\r\n\r\n", ParseError::UnsupportedVersion),
+            (b"GET / HTTP/2.0\r\n\r\n", ParseError::UnsupportedVersion),
+            (b"GET / HTTP/one\r\n\r\n", ParseError::BadRequestLine),
+            (b"GET /  HTTP/1.1\r\n\r\n", ParseError::BadRequestLine),
+        ];
+
+        for (raw, error) in cases {
+            assert_eq!(HttpRequest::try_from(raw).unwrap_err(), error);
+        }
+    }
+
+    #[test]
+    fn test_parse_too_large() {
+        let mut parser = RequestParser::new();
+        let long_line = format!("GET /{} HTTP/1.1", "a".repeat(MAX_REQUEST_LINE_LEN));
+
+        assert_eq!(
+            parser.parse(long_line.as_bytes()).unwrap_err(),
+            ParseError::UriTooLong
+        );
+
+        let mut parser = RequestParser::new();
+        let mut huge_head = String::from("GET / HTTP/1.1\r\n");
+
+        while huge_head.len() <= MAX_HEAD_LEN {
+            huge_head.push_str("X-Padding: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n");
+        }
+
+        assert_eq!(
+            parser.parse(huge_head.as_bytes()).unwrap_err(),
+            ParseError::HeadersTooLarge
+        );
+    }
+
+    #[test]
+    fn test_parse_target_forms() {
+        let resource = Rescourse::parse(&Method::Get, "/api/orders?status=true&page=2").unwrap();
+
+        assert_eq!(resource.path(), "/api/orders");
+        assert_eq!(resource.query().unwrap().get("status"), Some("true"));
+        assert_eq!(resource.query().unwrap().get("page"), Some("2"));
+
+        let resource = Rescourse::parse(&Method::Get, "/public/../%2e%2e//404.html").unwrap();
+
+        assert_eq!(resource.path(), "/404.html");
+
+        let resource = Rescourse::parse(&Method::Get, "HTTP://localhost:3000?a=1").unwrap();
+
+        assert_eq!(
+            resource,
+            Rescourse::Absolute {
+                scheme: "http".into(),
+                authority: "localhost:3000".into(),
+                path: UriPath::default(),
+                query: Query::parse("a=1").unwrap(),
+            }
+        );
+        assert_eq!(
+            Rescourse::parse(&Method::Connect, "localhost:443"),
+            Ok(Rescourse::Authority("localhost:443".into()))
+        );
+        assert_eq!(
+            Rescourse::parse(&Method::Options, "*"),
+            Ok(Rescourse::Asterisk)
+        );
+    }
+
+    #[test]
+    fn test_parse_bad_targets() {
+        let cases = [
+            (Method::Get, "*"),
+            (Method::Get, "localhost:443"),
+            (Method::Connect, "/"),
+            (Method::Connect, "localhost"),
+            (Method::Get, "/a#fragment"),
+            (Method::Get, "/a?b=%zz"),
+            (Method::Get, "/a%00b"),
+            (Method::Get, "/..%2f..%2fetc"),
+            (Method::Get, "1http://localhost/"),
+        ];
+
+        for (method, target) in cases {
+            assert_eq!(
+                Rescourse::parse(&method, target),
+                Err(ParseError::BadTarget),
+                "{} {}",
+                method,
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_duplicate_headers() {
+        let raw = b"GET / HTTP/1.1\r\nCookie: a=1\r\nHost: localhost\r\ncookie: b=2\r\n\r\n";
+        let request = HttpRequest::try_from(&raw[..]).unwrap();
+
+        assert_eq!(
+            request.headers.get_all("Cookie").collect::<Vec<_>>(),
+            vec!["a=1", "b=2"]
+        );
+        assert_eq!(request.headers.get("HOST"), Some("localhost"));
+    }
+
+    #[test]
+    fn test_parse_body() {
+        let raw =
+            b"POST /upload HTTP/1.1\r\nHost: a\r\nContent-Length: 6\r\n\r\n\x89PNG\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\n";
+        let mut parser = RequestParser::new();
+        let head_len = raw.len() - 6 - 27;
+
+        for end in 0..head_len + 6 {
+            assert!(matches!(
+                parser.parse(&raw[..end]),
+                Ok(ParseStatus::Incomplete)
+            ));
+        }
+
+        let consumed = match parser.parse(raw) {
+            Ok(ParseStatus::Complete(request, consumed)) => {
+                assert_eq!(request.body, Some(b"\x89PNG\r\n".to_vec()));
+                consumed
+            }
+            other => panic!("unexpected parse result: {:?}", other),
+        };
+
+        assert_eq!(consumed, head_len + 6);
+
+        match parser.parse(&raw[consumed..]) {
+            Ok(ParseStatus::Complete(request, _)) => assert_eq!(request.body, None),
+            other => panic!("unexpected parse result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_body_errors() {
+        let too_large = format!(
+            "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_LEN + 1
+        );
+        let cases: [(&[u8], ParseError); 4] = [
+            (too_large.as_bytes(), ParseError::BodyTooLarge),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: -1\r\n\r\n",
+                ParseError::BadHeader,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 0x10\r\n\r\n",
+                ParseError::BadHeader,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 10\r\n\r\nshort",
+                ParseError::UnexpectedEof,
+            ),
+        ];
+
+        for (raw, error) in cases {
+            assert_eq!(HttpRequest::try_from(raw).unwrap_err(), error);
+        }
+    }
+
+    #[test]
+    fn test_parse_chunked_body() {
+        let raw = b"POST /upload HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip, chunked\r\n\r\n";
+
+        assert_eq!(
+            HttpRequest::try_from(&raw[..]).unwrap_err(),
+            ParseError::UnsupportedTransferCoding
+        );
+
+        let raw = b"POST /upload HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5;name=\"value\"\r\nhello\r\n\
+            7 ; ext\r\n, world\r\n\
+            0\r\n\
+            Checksum: abc\r\n\
+            \r\n\
+            GET / HTTP/1.1\r\nHost: a\r\n\r\n";
+        let mut parser = RequestParser::new();
+        let request_len = raw.len() - 27;
+
+        for end in 0..request_len {
+            assert!(matches!(
+                parser.parse(&raw[..end]),
+                Ok(ParseStatus::Incomplete)
+            ));
+        }
+
+        match parser.parse(raw) {
+            Ok(ParseStatus::Complete(request, consumed)) => {
+                assert_eq!(consumed, request_len);
+                assert_eq!(request.body, Some(b"hello, world".to_vec()));
+                assert_eq!(request.trailers.get("checksum"), Some("abc"));
+            }
+            other => panic!("unexpected parse result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_chunked_decoder_errors() {
+        let cases: [(&[u8], ParseError); 7] = [
+            (b"z\r\n", ParseError::BadChunk),
+            (b"5 \r\nhello\r\n", ParseError::BadChunk),
+            (b"5\r\nhelloX\r\n", ParseError::BadChunk),
+            (b"5;=x\r\nhello\r\n", ParseError::BadChunk),
+            (b"ffffffffffffffffff\r\n", ParseError::BadChunk),
+            (b"11\r\n", ParseError::BodyTooLarge),
+            (b"1\r\na\r\nffffffffffffffff\r\n", ParseError::BodyTooLarge),
+        ];
+
+        for (raw, error) in cases {
+            assert_eq!(ChunkedDecoder::new(16, false).decode(raw), Err(error));
+        }
+
+        // Extensions on 1-byte chunks grow the encoded body, not the decoded one
+        let raw = b"1;ext=value\r\nx\r\n".repeat(1024);
+
+        assert_eq!(
+            ChunkedDecoder::new(1024, false).decode(&raw),
+            Err(ParseError::BodyTooLarge)
+        );
+
+        let config = ParserConfig {
+            max_head: 8,
+            ..ParserConfig::default()
+        };
+
+        assert_eq!(
+            ChunkedDecoder::with_config(&config).decode(b"0\r\nX-Trailer: long\r\n\r\n"),
+            Err(ParseError::HeadersTooLarge)
+        );
+
+        let mut decoder = ChunkedDecoder::new(16, false);
+
+        assert_eq!(decoder.decode(b"0\r\n\r\n"), Ok(Some(5)));
+        assert_eq!(decoder.take(), (Vec::new(), HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_parse_strict() {
+        let strict = ParserConfig::strict();
+        let cases: [(&[u8], ParseError); 8] = [
+            (
+                b"GET / HTTP/1.1\nHost: a\r\n\r\n",
+                ParseError::BadRequestLine,
+            ),
+            (b"GET / HTTP/1.1\r\nHost: a\n\r\n", ParseError::BadHeader),
+            (
+                b"GET / HTTP/1.1\r\nHost: a\rb\r\n\r\n",
+                ParseError::BadHeader,
+            ),
+            (b"GET / HTTP/1.1\r\nHost : a\r\n\r\n", ParseError::BadHeader),
+            (
+                b"GET / HTTP/1.1\r\nHost: a\r\n folded\r\n\r\n",
+                ParseError::BadHeader,
+            ),
+            (b"GET / HTTP/1.1\r\nX(Y): a\r\n\r\n", ParseError::BadHeader),
+            (
+                b"GET / HTTP/1.1\r\nX: a\x01b\r\n\r\n",
+                ParseError::BadHeader,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nX: a\x7fb\r\n\r\n",
+                ParseError::BadHeader,
+            ),
+        ];
+
+        for (raw, error) in cases {
+            let mut parser = RequestParser::with_config(strict.clone());
+
+            assert_eq!(parser.parse(raw).unwrap_err(), error, "{:?}", raw);
+        }
+
+        let mut parser = RequestParser::with_config(strict);
+        let raw = b"GET / HTTP/1.1\r\nHost:localhost\r\nX-Empty:\r\nX-Tab:\ta\tb \r\n\r\n";
+
+        match parser.parse(raw) {
+            Ok(ParseStatus::Complete(request, _)) => {
+                assert_eq!(request.headers.get("Host"), Some("localhost"));
+                assert_eq!(request.headers.get("X-Empty"), Some(""));
+                assert_eq!(request.headers.get("X-Tab"), Some("a\tb"));
+            }
+            other => panic!("unexpected parse result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let config = ParserConfig {
+            max_request_line: 16,
+            max_header_line: 20,
+            max_headers: 2,
+            max_head: 64,
+            max_body: 4,
+            ..ParserConfig::strict()
+        };
+        let cases: [(&[u8], ParseError); 8] = [
+            (b"GET /0123456789 HTTP/1.1\r\n", ParseError::UriTooLong),
+            // Empty lines before the request line count against the limits too
+            (&[b'\n'; 17], ParseError::BadRequestLine),
+            (
+                b"\r\n\r\n\r\n\r\n\r\n\r\n\r\nGET / HTTP/1.1\r\nA: 0123456789abcdef\r\nB: 0123456789abcdef",
+                ParseError::HeadersTooLarge,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nX: 0123456789abcdefghij",
+                ParseError::HeadersTooLarge,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nX: 0123456789abcdefghij\r\n\r\n",
+                ParseError::HeadersTooLarge,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n",
+                ParseError::HeadersTooLarge,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\nE: 5\r\nF: 6\r\nG: 7\r\nH: 8\r\nI: 9\r\n",
+                ParseError::HeadersTooLarge,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\n",
+                ParseError::BodyTooLarge,
+            ),
+        ];
+
+        for (raw, error) in cases {
+            let mut parser = RequestParser::with_config(config.clone());
+
+            assert_eq!(parser.parse(raw).unwrap_err(), error, "{:?}", raw);
+        }
+
+        // Past `max_request_len` a request is either complete or rejected
+        let config = ParserConfig {
+            max_body: 64,
+            ..ParserConfig::strict()
+        };
+        let mut raw = b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+
+        while raw.len() <= config.max_request_len() {
+            raw.extend_from_slice(b"1;a=b\r\nx\r\n");
+        }
+
+        let mut parser = RequestParser::with_config(config);
+
+        assert_eq!(parser.parse(&raw).unwrap_err(), ParseError::BodyTooLarge);
+    }
+
+    #[test]
+    fn test_parse_smuggling() {
+        let cases: [(&[u8], ParseError); 10] = [
+            // CL.TE and TE.CL
+            (
+                b"POST / HTTP/1.1\r\nContent-Length: 13\r\nTransfer-Encoding: chunked\r\n\r\n\
+                0\r\n\r\nSMUGGLED",
+                ParseError::AmbiguousFraming,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n\
+                8\r\nSMUGGLED\r\n0\r\n\r\n",
+                ParseError::AmbiguousFraming,
+            ),
+            // TE.TE, where one side ignores an obfuscated coding
+            (
+                b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: x\r\n\r\n",
+                ParseError::AmbiguousFraming,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nTransfer-Encoding: xchunked\r\n\r\n",
+                ParseError::AmbiguousFraming,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nTransfer-Encoding : chunked\r\n\r\n",
+                ParseError::BadHeader,
+            ),
+            (
+                b"POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+                ParseError::AmbiguousFraming,
+            ),
+            // CL.CL
+            (
+                b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\nhello!",
+                ParseError::AmbiguousFraming,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nContent-Length: 5, 6\r\n\r\nhello!",
+                ParseError::AmbiguousFraming,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nContent-Length : 5\r\n\r\nhello",
+                ParseError::BadHeader,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nContent-Length: +5\r\n\r\nhello",
+                ParseError::BadHeader,
+            ),
+        ];
+
+        for (raw, error) in cases {
+            let mut parser = RequestParser::with_config(ParserConfig::strict());
+
+            assert_eq!(HttpRequest::try_from(raw).unwrap_err(), error, "{:?}", raw);
+            assert_eq!(parser.parse(raw).unwrap_err(), error, "{:?}", raw);
+        }
+
+        // A field folded onto the previous line hides it from proxies that unfold it
+        for raw in [
+            &b"POST / HTTP/1.1\r\n Transfer-Encoding: chunked\r\n\r\n"[..],
+            &b"POST / HTTP/1.1\r\nX: y\r\n\tTransfer-Encoding: chunked\r\n\r\n"[..],
+        ] {
+            let mut parser = RequestParser::with_config(ParserConfig::strict());
+
+            assert_eq!(parser.parse(raw).unwrap_err(), ParseError::BadHeader);
+            assert_eq!(
+                HttpRequest::try_from(raw).unwrap_err(),
+                ParseError::BadHeader
+            );
+        }
+
+        for raw in [
+            &b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\nhello"[..],
+            &b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5, 5\r\n\r\nhello"[..],
+        ] {
+            assert_eq!(
+                HttpRequest::try_from(raw).unwrap().body,
+                Some(b"hello".to_vec())
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_host() {
+        let cases: [(&[u8], Result<(), ParseError>); 5] = [
+            (b"GET / HTTP/1.1\r\nHost: a\r\n\r\n", Ok(())),
+            (b"GET / HTTP/1.0\r\n\r\n", Ok(())),
+            (b"GET / HTTP/1.1\r\n\r\n", Err(ParseError::MissingHost)),
+            (
+                b"GET http://a/ HTTP/1.1\r\n\r\n",
+                Err(ParseError::MissingHost),
+            ),
+            (
+                b"GET / HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n",
+                Err(ParseError::BadHeader),
+            ),
+        ];
+
+        for (raw, result) in cases {
+            for config in [ParserConfig::default(), ParserConfig::strict()] {
+                let mut parser = RequestParser::with_config(config);
+
+                assert_eq!(parser.finish(raw).map(|_| ()), result, "{:?}", raw);
+            }
+        }
+
+        assert_eq!(
+            HttpRequest::try_from(&b"GET / HTTP/1.1\r\n\r\n"[..]).unwrap_err(),
+            ParseError::MissingHost
+        );
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        let cases: [(&[u8], bool); 6] = [
+            (b"GET / HTTP/1.1\r\nHost: a\r\n\r\n", true),
+            (
+                b"GET / HTTP/1.1\r\nHost: a\r\nConnection: Close\r\n\r\n",
+                false,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nHost: a\r\nConnection: te, close\r\n\r\n",
+                false,
+            ),
+            (b"GET / HTTP/1.0\r\n\r\n", false),
+            (b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n", true),
+            (b"GET / HTTP/1.0\r\nConnection: closed\r\n\r\n", false),
+        ];
+
+        for (raw, keep_alive) in cases {
+            let request = HttpRequest::try_from(raw).unwrap();
+
+            assert_eq!(request.keep_alive(), keep_alive, "{:?}", raw);
+        }
+    }
 }
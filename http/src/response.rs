@@ -1,117 +1,333 @@
-use std::{collections::HashMap, error::Error, io::Write};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Take, Write};
+use std::path::Path;
 
-use crate::request::Version;
+use serde::Serialize;
 
-#[derive(Debug, PartialEq)]
+use crate::body::Body;
+use crate::date::HttpDate;
+use crate::header::{is_field_vchar, HeaderError, HeaderMap, HeaderName};
+use crate::mime;
+use crate::request::{HttpRequest, Method, ParseError, Version};
+
+/// Response status codes (RFC 9110 §15)
+///
+/// Codes without a variant of their own, or a code that needs a different reason
+/// phrase, go through [`HttpStatus::custom`].
+#[derive(Debug, Clone, PartialEq)]
 #[allow(non_camel_case_types)]
 pub enum HttpStatus {
-    CONTINUE = 100,
-    SWITCHING_PROTOCOLS = 101,
-    PROCESSING = 102,
-    EARLYHINTS = 103,
-    OK = 200,
-    CREATED = 201,
-    ACCEPTED = 202,
-    NON_AUTHORITATIVE_INFORMATION = 203,
-    NO_CONTENT = 204,
-    RESET_CONTENT = 205,
-    PARTIAL_CONTENT = 206,
-    AMBIGUOUS = 300,
-    MOVED_PERMANENTLY = 301,
-    FOUND = 302,
-    SEE_OTHER = 303,
-    NOT_MODIFIED = 304,
-    TEMPORARY_REDIRECT = 307,
-    PERMANENT_REDIRECT = 308,
-    BAD_REQUEST = 400,
-    UNAUTHORIZED = 401,
-    PAYMENT_REQUIRED = 402,
-    FORBIDDEN = 403,
-    NOT_FOUND = 404,
-    METHOD_NOT_ALLOWED = 405,
-    NOT_ACCEPTABLE = 406,
-    PROXY_AUTHENTICATION_REQUIRED = 407,
-    REQUEST_TIMEOUT = 408,
-    CONFLICT = 409,
-    GONE = 410,
-    LENGTH_REQUIRED = 411,
-    PRECONDITION_FAILED = 412,
-    PAYLOAD_TOO_LARGE = 413,
-    URI_TOO_LONG = 414,
-    UNSUPPORTED_MEDIA_TYPE = 415,
-    REQUESTED_RANGE_NOT_SATISFIABLE = 416,
-    EXPECTATION_FAILED = 417,
-    I_AM_A_TEAPOT = 418,
-    MISDIRECTED = 421,
-    UNPROCESSABLE_ENTITY = 422,
-    FAILED_DEPENDENCY = 424,
-    PRECONDITION_REQUIRED = 428,
-    TOO_MANY_REQUESTS = 429,
-    INTERNAL_SERVER_ERROR = 500,
-    NOT_IMPLEMENTED = 501,
-    BAD_GATEWAY = 502,
-    SERVICE_UNAVAILABLE = 503,
-    GATEWAY_TIMEOUT = 504,
-    HTTP_VERSION_NOT_SUPPORTED = 505,
+    CONTINUE,
+    SWITCHING_PROTOCOLS,
+    PROCESSING,
+    EARLYHINTS,
+    OK,
+    CREATED,
+    ACCEPTED,
+    NON_AUTHORITATIVE_INFORMATION,
+    NO_CONTENT,
+    RESET_CONTENT,
+    PARTIAL_CONTENT,
+    MULTI_STATUS,
+    ALREADY_REPORTED,
+    IM_USED,
+    AMBIGUOUS,
+    MOVED_PERMANENTLY,
+    FOUND,
+    SEE_OTHER,
+    NOT_MODIFIED,
+    TEMPORARY_REDIRECT,
+    PERMANENT_REDIRECT,
+    BAD_REQUEST,
+    UNAUTHORIZED,
+    PAYMENT_REQUIRED,
+    FORBIDDEN,
+    NOT_FOUND,
+    METHOD_NOT_ALLOWED,
+    NOT_ACCEPTABLE,
+    PROXY_AUTHENTICATION_REQUIRED,
+    REQUEST_TIMEOUT,
+    CONFLICT,
+    GONE,
+    LENGTH_REQUIRED,
+    PRECONDITION_FAILED,
+    PAYLOAD_TOO_LARGE,
+    URI_TOO_LONG,
+    UNSUPPORTED_MEDIA_TYPE,
+    REQUESTED_RANGE_NOT_SATISFIABLE,
+    EXPECTATION_FAILED,
+    I_AM_A_TEAPOT,
+    MISDIRECTED,
+    UNPROCESSABLE_ENTITY,
+    LOCKED,
+    FAILED_DEPENDENCY,
+    TOO_EARLY,
+    UPGRADE_REQUIRED,
+    PRECONDITION_REQUIRED,
+    TOO_MANY_REQUESTS,
+    REQUEST_HEADER_FIELDS_TOO_LARGE,
+    UNAVAILABLE_FOR_LEGAL_REASONS,
+    INTERNAL_SERVER_ERROR,
+    NOT_IMPLEMENTED,
+    BAD_GATEWAY,
+    SERVICE_UNAVAILABLE,
+    GATEWAY_TIMEOUT,
+    HTTP_VERSION_NOT_SUPPORTED,
+    VARIANT_ALSO_NEGOTIATES,
+    INSUFFICIENT_STORAGE,
+    LOOP_DETECTED,
+    NOT_EXTENDED,
+    NETWORK_AUTHENTICATION_REQUIRED,
+    Custom(CustomStatus),
 }
 
-impl Into<(u16, String)> for &HttpStatus {
-    fn into(self) -> (u16, String) {
+/// A status code and reason phrase that were checked to fit in a status line
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomStatus {
+    code: u16,
+    reason: String,
+}
+
+/// A status code or reason phrase that can't be sent
+#[derive(Debug, PartialEq)]
+pub enum InvalidStatus {
+    /// Codes are three digits with a class from 1xx to 5xx
+    Code(u16),
+    /// The reason phrase contains control characters such as CR or LF
+    Reason(String),
+}
+
+impl fmt::Display for InvalidStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            HttpStatus::CONTINUE => (100, "Continue".into()),
-            HttpStatus::SWITCHING_PROTOCOLS => (101, "Switching Protocols".into()),
-            HttpStatus::PROCESSING => (102, "Processing".into()),
-            HttpStatus::EARLYHINTS => (103, "Earlyhints".into()),
-            HttpStatus::OK => (200, String::from("Ok")),
-            HttpStatus::CREATED => (201, "Created".into()),
-            HttpStatus::ACCEPTED => (202, "Accepted".into()),
-            HttpStatus::NON_AUTHORITATIVE_INFORMATION => {
-                (203, "Non Authoritative Information".into())
-            }
-            HttpStatus::NO_CONTENT => (204, "No Content".into()),
-            HttpStatus::RESET_CONTENT => (205, "Reset Content".into()),
-            HttpStatus::PARTIAL_CONTENT => (206, "Partial Content".into()),
-            HttpStatus::AMBIGUOUS => (300, "Ambiguous".into()),
-            HttpStatus::MOVED_PERMANENTLY => (301, "Moved Permanently".into()),
-            HttpStatus::FOUND => (302, "Found".into()),
-            HttpStatus::SEE_OTHER => (303, "See Other".into()),
-            HttpStatus::NOT_MODIFIED => (304, "Not Modfifed".into()),
-            HttpStatus::TEMPORARY_REDIRECT => (307, "Temporary Redirect".into()),
-            HttpStatus::PERMANENT_REDIRECT => (308, "Permanent Redirect".into()),
-            HttpStatus::BAD_REQUEST => (400, "Bad Request".into()),
-            HttpStatus::UNAUTHORIZED => (401, "Unauthorized".into()),
-            HttpStatus::PAYMENT_REQUIRED => (402, "Payment Required".into()),
-            HttpStatus::FORBIDDEN => (403, "Forbidden".into()),
-            HttpStatus::NOT_FOUND => (404, "Not Found".into()),
-            HttpStatus::METHOD_NOT_ALLOWED => (405, "Method Not Allowed".into()),
-            HttpStatus::NOT_ACCEPTABLE => (406, "Not Acceptable".into()),
-            HttpStatus::PROXY_AUTHENTICATION_REQUIRED => {
-                (407, "Proxy Authentication Required".into())
-            }
-            HttpStatus::REQUEST_TIMEOUT => (408, "Request Timeout".into()),
-            HttpStatus::CONFLICT => (409, "Conflict".into()),
-            HttpStatus::GONE => (401, "Gone".into()),
-            HttpStatus::LENGTH_REQUIRED => (411, "Length Required".into()),
-            HttpStatus::PRECONDITION_FAILED => (412, "Precondition Failed".into()),
-            HttpStatus::PAYLOAD_TOO_LARGE => (413, "Payload Too Large".into()),
-            HttpStatus::URI_TOO_LONG => (414, "URI Too Long".into()),
-            HttpStatus::UNSUPPORTED_MEDIA_TYPE => (415, "Unsupported Media Type".into()),
-            HttpStatus::REQUESTED_RANGE_NOT_SATISFIABLE => {
-                (416, "Required Range Not Satisfiable".into())
-            }
-            HttpStatus::EXPECTATION_FAILED => (417, "Expectation Failed".into()),
-            HttpStatus::I_AM_A_TEAPOT => (418, "I Am A Teapot".into()),
-            HttpStatus::MISDIRECTED => (421, "Misdirected".into()),
-            HttpStatus::UNPROCESSABLE_ENTITY => (422, "Unprocessable Entity".into()),
-            HttpStatus::FAILED_DEPENDENCY => (424, "Failed Dependency".into()),
-            HttpStatus::PRECONDITION_REQUIRED => (428, "Precondition Required".into()),
-            HttpStatus::TOO_MANY_REQUESTS => (429, "Too Many Requests".into()),
-            HttpStatus::INTERNAL_SERVER_ERROR => (500, "Internal Server Error".into()),
-            HttpStatus::NOT_IMPLEMENTED => (501, "Not Implemented".into()),
-            HttpStatus::BAD_GATEWAY => (502, "Bad Gateway".into()),
-            HttpStatus::SERVICE_UNAVAILABLE => (503, "Service Unavailable".into()),
-            HttpStatus::GATEWAY_TIMEOUT => (504, "Gateway Timeout".into()),
-            HttpStatus::HTTP_VERSION_NOT_SUPPORTED => (505, "Http Version Not Supported".into()),
+            InvalidStatus::Code(code) => write!(f, "invalid status code {}", code),
+            InvalidStatus::Reason(reason) => write!(f, "invalid reason phrase {:?}", reason),
+        }
+    }
+}
+
+impl Error for InvalidStatus {}
+
+impl HttpStatus {
+    /// A status with any code from 100 to 599 and its own reason phrase
+    pub fn custom(code: u16, reason: impl Into<String>) -> Result<HttpStatus, InvalidStatus> {
+        let reason = reason.into();
+
+        if !(100..600).contains(&code) {
+            return Err(InvalidStatus::Code(code));
+        }
+
+        if !reason.bytes().all(is_field_vchar) {
+            return Err(InvalidStatus::Reason(reason));
+        }
+
+        Ok(HttpStatus::Custom(CustomStatus { code, reason }))
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        self.parts().0
+    }
+
+    /// Reason phrase sent in the status line
+    pub fn reason(&self) -> &str {
+        self.parts().1
+    }
+
+    /// 1xx
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.as_u16())
+    }
+
+    /// 2xx
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.as_u16())
+    }
+
+    /// 3xx
+    pub fn is_redirect(&self) -> bool {
+        (300..400).contains(&self.as_u16())
+    }
+
+    /// 4xx
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.as_u16())
+    }
+
+    /// 5xx
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.as_u16())
+    }
+
+    fn parts(&self) -> (u16, &str) {
+        match self {
+            HttpStatus::CONTINUE => (100, "Continue"),
+            HttpStatus::SWITCHING_PROTOCOLS => (101, "Switching Protocols"),
+            HttpStatus::PROCESSING => (102, "Processing"),
+            HttpStatus::EARLYHINTS => (103, "Early Hints"),
+            HttpStatus::OK => (200, "OK"),
+            HttpStatus::CREATED => (201, "Created"),
+            HttpStatus::ACCEPTED => (202, "Accepted"),
+            HttpStatus::NON_AUTHORITATIVE_INFORMATION => (203, "Non-Authoritative Information"),
+            HttpStatus::NO_CONTENT => (204, "No Content"),
+            HttpStatus::RESET_CONTENT => (205, "Reset Content"),
+            HttpStatus::PARTIAL_CONTENT => (206, "Partial Content"),
+            HttpStatus::MULTI_STATUS => (207, "Multi-Status"),
+            HttpStatus::ALREADY_REPORTED => (208, "Already Reported"),
+            HttpStatus::IM_USED => (226, "IM Used"),
+            HttpStatus::AMBIGUOUS => (300, "Multiple Choices"),
+            HttpStatus::MOVED_PERMANENTLY => (301, "Moved Permanently"),
+            HttpStatus::FOUND => (302, "Found"),
+            HttpStatus::SEE_OTHER => (303, "See Other"),
+            HttpStatus::NOT_MODIFIED => (304, "Not Modified"),
+            HttpStatus::TEMPORARY_REDIRECT => (307, "Temporary Redirect"),
+            HttpStatus::PERMANENT_REDIRECT => (308, "Permanent Redirect"),
+            HttpStatus::BAD_REQUEST => (400, "Bad Request"),
+            HttpStatus::UNAUTHORIZED => (401, "Unauthorized"),
+            HttpStatus::PAYMENT_REQUIRED => (402, "Payment Required"),
+            HttpStatus::FORBIDDEN => (403, "Forbidden"),
+            HttpStatus::NOT_FOUND => (404, "Not Found"),
+            HttpStatus::METHOD_NOT_ALLOWED => (405, "Method Not Allowed"),
+            HttpStatus::NOT_ACCEPTABLE => (406, "Not Acceptable"),
+            HttpStatus::PROXY_AUTHENTICATION_REQUIRED => (407, "Proxy Authentication Required"),
+            HttpStatus::REQUEST_TIMEOUT => (408, "Request Timeout"),
+            HttpStatus::CONFLICT => (409, "Conflict"),
+            HttpStatus::GONE => (410, "Gone"),
+            HttpStatus::LENGTH_REQUIRED => (411, "Length Required"),
+            HttpStatus::PRECONDITION_FAILED => (412, "Precondition Failed"),
+            HttpStatus::PAYLOAD_TOO_LARGE => (413, "Content Too Large"),
+            HttpStatus::URI_TOO_LONG => (414, "URI Too Long"),
+            HttpStatus::UNSUPPORTED_MEDIA_TYPE => (415, "Unsupported Media Type"),
+            HttpStatus::REQUESTED_RANGE_NOT_SATISFIABLE => (416, "Range Not Satisfiable"),
+            HttpStatus::EXPECTATION_FAILED => (417, "Expectation Failed"),
+            HttpStatus::I_AM_A_TEAPOT => (418, "I'm a teapot"),
+            HttpStatus::MISDIRECTED => (421, "Misdirected Request"),
+            HttpStatus::UNPROCESSABLE_ENTITY => (422, "Unprocessable Content"),
+            HttpStatus::LOCKED => (423, "Locked"),
+            HttpStatus::FAILED_DEPENDENCY => (424, "Failed Dependency"),
+            HttpStatus::TOO_EARLY => (425, "Too Early"),
+            HttpStatus::UPGRADE_REQUIRED => (426, "Upgrade Required"),
+            HttpStatus::PRECONDITION_REQUIRED => (428, "Precondition Required"),
+            HttpStatus::TOO_MANY_REQUESTS => (429, "Too Many Requests"),
+            HttpStatus::REQUEST_HEADER_FIELDS_TOO_LARGE => (431, "Request Header Fields Too Large"),
+            HttpStatus::UNAVAILABLE_FOR_LEGAL_REASONS => (451, "Unavailable For Legal Reasons"),
+            HttpStatus::INTERNAL_SERVER_ERROR => (500, "Internal Server Error"),
+            HttpStatus::NOT_IMPLEMENTED => (501, "Not Implemented"),
+            HttpStatus::BAD_GATEWAY => (502, "Bad Gateway"),
+            HttpStatus::SERVICE_UNAVAILABLE => (503, "Service Unavailable"),
+            HttpStatus::GATEWAY_TIMEOUT => (504, "Gateway Timeout"),
+            HttpStatus::HTTP_VERSION_NOT_SUPPORTED => (505, "HTTP Version Not Supported"),
+            HttpStatus::VARIANT_ALSO_NEGOTIATES => (506, "Variant Also Negotiates"),
+            HttpStatus::INSUFFICIENT_STORAGE => (507, "Insufficient Storage"),
+            HttpStatus::LOOP_DETECTED => (508, "Loop Detected"),
+            HttpStatus::NOT_EXTENDED => (510, "Not Extended"),
+            HttpStatus::NETWORK_AUTHENTICATION_REQUIRED => (511, "Network Authentication Required"),
+            HttpStatus::Custom(status) => (status.code, &status.reason),
+        }
+    }
+}
+
+/// Codes without a variant become a custom status with an empty reason phrase
+impl TryFrom<u16> for HttpStatus {
+    type Error = InvalidStatus;
+
+    fn try_from(code: u16) -> Result<HttpStatus, InvalidStatus> {
+        Ok(match code {
+            100 => HttpStatus::CONTINUE,
+            101 => HttpStatus::SWITCHING_PROTOCOLS,
+            102 => HttpStatus::PROCESSING,
+            103 => HttpStatus::EARLYHINTS,
+            200 => HttpStatus::OK,
+            201 => HttpStatus::CREATED,
+            202 => HttpStatus::ACCEPTED,
+            203 => HttpStatus::NON_AUTHORITATIVE_INFORMATION,
+            204 => HttpStatus::NO_CONTENT,
+            205 => HttpStatus::RESET_CONTENT,
+            206 => HttpStatus::PARTIAL_CONTENT,
+            207 => HttpStatus::MULTI_STATUS,
+            208 => HttpStatus::ALREADY_REPORTED,
+            226 => HttpStatus::IM_USED,
+            300 => HttpStatus::AMBIGUOUS,
+            301 => HttpStatus::MOVED_PERMANENTLY,
+            302 => HttpStatus::FOUND,
+            303 => HttpStatus::SEE_OTHER,
+            304 => HttpStatus::NOT_MODIFIED,
+            307 => HttpStatus::TEMPORARY_REDIRECT,
+            308 => HttpStatus::PERMANENT_REDIRECT,
+            400 => HttpStatus::BAD_REQUEST,
+            401 => HttpStatus::UNAUTHORIZED,
+            402 => HttpStatus::PAYMENT_REQUIRED,
+            403 => HttpStatus::FORBIDDEN,
+            404 => HttpStatus::NOT_FOUND,
+            405 => HttpStatus::METHOD_NOT_ALLOWED,
+            406 => HttpStatus::NOT_ACCEPTABLE,
+            407 => HttpStatus::PROXY_AUTHENTICATION_REQUIRED,
+            408 => HttpStatus::REQUEST_TIMEOUT,
+            409 => HttpStatus::CONFLICT,
+            410 => HttpStatus::GONE,
+            411 => HttpStatus::LENGTH_REQUIRED,
+            412 => HttpStatus::PRECONDITION_FAILED,
+            413 => HttpStatus::PAYLOAD_TOO_LARGE,
+            414 => HttpStatus::URI_TOO_LONG,
+            415 => HttpStatus::UNSUPPORTED_MEDIA_TYPE,
+            416 => HttpStatus::REQUESTED_RANGE_NOT_SATISFIABLE,
+            417 => HttpStatus::EXPECTATION_FAILED,
+            418 => HttpStatus::I_AM_A_TEAPOT,
+            421 => HttpStatus::MISDIRECTED,
+            422 => HttpStatus::UNPROCESSABLE_ENTITY,
+            423 => HttpStatus::LOCKED,
+            424 => HttpStatus::FAILED_DEPENDENCY,
+            425 => HttpStatus::TOO_EARLY,
+            426 => HttpStatus::UPGRADE_REQUIRED,
+            428 => HttpStatus::PRECONDITION_REQUIRED,
+            429 => HttpStatus::TOO_MANY_REQUESTS,
+            431 => HttpStatus::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            451 => HttpStatus::UNAVAILABLE_FOR_LEGAL_REASONS,
+            500 => HttpStatus::INTERNAL_SERVER_ERROR,
+            501 => HttpStatus::NOT_IMPLEMENTED,
+            502 => HttpStatus::BAD_GATEWAY,
+            503 => HttpStatus::SERVICE_UNAVAILABLE,
+            504 => HttpStatus::GATEWAY_TIMEOUT,
+            505 => HttpStatus::HTTP_VERSION_NOT_SUPPORTED,
+            506 => HttpStatus::VARIANT_ALSO_NEGOTIATES,
+            507 => HttpStatus::INSUFFICIENT_STORAGE,
+            508 => HttpStatus::LOOP_DETECTED,
+            510 => HttpStatus::NOT_EXTENDED,
+            511 => HttpStatus::NETWORK_AUTHENTICATION_REQUIRED,
+            code => HttpStatus::custom(code, "")?,
+        })
+    }
+}
+
+impl From<&HttpStatus> for (u16, String) {
+    fn from(status: &HttpStatus) -> (u16, String) {
+        (status.as_u16(), status.reason().into())
+    }
+}
+
+impl fmt::Display for HttpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.as_u16(), self.reason())
+    }
+}
+
+impl From<&ParseError> for HttpStatus {
+    fn from(error: &ParseError) -> HttpStatus {
+        match error {
+            ParseError::BadRequestLine
+            | ParseError::BadHeader
+            | ParseError::InvalidMethod
+            | ParseError::BadTarget
+            | ParseError::BadChunk
+            | ParseError::AmbiguousFraming
+            | ParseError::MissingHost => HttpStatus::BAD_REQUEST,
+            ParseError::UnexpectedEof => HttpStatus::BAD_REQUEST,
+            ParseError::UnsupportedVersion => HttpStatus::HTTP_VERSION_NOT_SUPPORTED,
+            ParseError::UriTooLong => HttpStatus::URI_TOO_LONG,
+            ParseError::HeadersTooLarge => HttpStatus::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            ParseError::BodyTooLarge => HttpStatus::PAYLOAD_TOO_LARGE,
+            ParseError::UnsupportedTransferCoding => HttpStatus::NOT_IMPLEMENTED,
         }
     }
 }
@@ -119,8 +335,21 @@ impl Into<(u16, String)> for &HttpStatus {
 pub struct HttpResponse {
     version: Version,
     status_code: HttpStatus,
-    headers: Option<HashMap<String, String>>,
-    body: Option<String>,
+    headers: HeaderMap,
+    body: Body,
+    /// Send the head, framing fields included, but not the body, as for `HEAD`
+    head_only: bool,
+}
+
+/// How the end of a response body is marked (RFC 9112 §6.3)
+enum Framing {
+    /// The status code forbids a body
+    NoBody,
+    Length(u64),
+    Chunked,
+    /// The body runs until the connection is closed, the only option for
+    /// HTTP/1.0 responses of unknown length
+    Close,
 }
 
 impl Default for HttpResponse {
@@ -128,92 +357,311 @@ impl Default for HttpResponse {
         HttpResponse {
             version: Version::V1_1,
             status_code: HttpStatus::OK,
-            headers: None,
-            body: None,
+            headers: HeaderMap::new(),
+            body: Body::Empty,
+            head_only: false,
         }
     }
 }
 
-impl From<&HttpResponse> for String {
-    fn from(response: &HttpResponse) -> String {
-        let status: (u16, String) = response.status_code().into();
-        let version: String = response.version().into();
-        let headers = response.headers();
-        let body = response.body();
-
-        format!(
-            "{} {} {}\r\n{}Content-Length: {}\r\n\r\n{}",
-            version,
-            status.0,
-            status.1,
-            headers,
-            body.len(),
-            body
-        )
-    }
-}
-
 impl HttpResponse {
     pub fn new(
         version: Version,
         status_code: HttpStatus,
-        headers: Option<HashMap<String, String>>,
-        body: Option<String>,
+        headers: Option<HeaderMap>,
+        body: impl Into<Body>,
     ) -> HttpResponse {
-        let mut response = HttpResponse::default();
+        HttpResponse {
+            version,
+            status_code,
+            headers: match headers {
+                Some(h) => h,
+                None => {
+                    let mut h = HeaderMap::new();
 
-        response.version = version;
-        response.status_code = status_code;
-        response.body = body;
-        response.headers = match headers {
-            Some(h) => Some(h),
-            None => {
-                let mut h = HashMap::new();
+                    h.insert("Content-Type", "text/html");
 
-                h.insert("Content-Type".into(), "text/html".into());
+                    h
+                }
+            },
+            body: body.into(),
+            head_only: false,
+        }
+    }
 
-                Some(h)
+    /// Start building a `200 OK` response with no headers and no body
+    pub fn builder() -> ResponseBuilder {
+        ResponseBuilder::default()
+    }
+
+    /// Write the response to the stream
+    ///
+    /// A header field that would split the response is never written: a bare 500 is sent
+    /// in place of the whole response and the offending field is returned as an
+    /// `InvalidData` error.
+    pub fn send(self, write_stream: &mut impl Write) -> io::Result<()> {
+        if let Some(mut file) = self.send_head(write_stream)? {
+            let length = file.limit();
+
+            // A file that shrank since would leave the body short of its `Content-Length`
+            if io::copy(&mut file, write_stream)? < length && length != u64::MAX {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            write_stream.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the response like [`HttpResponse::send`], except that a file body sent as is
+    /// is handed back instead of copied
+    ///
+    /// This lets a server move file bytes to the socket its own way. When a file is
+    /// returned, everything before the body has been written and exactly what the file
+    /// yields must follow. It is limited to the `Content-Length` that was sent, so a file
+    /// growing in the meantime can't overrun it, or unlimited when the body runs until
+    /// the connection closes.
+    pub fn send_head(self, write_stream: &mut impl Write) -> io::Result<Option<Take<File>>> {
+        let framing = self.framing();
+        let head = match self.head(&framing) {
+            Ok(head) => head,
+            Err(e) => {
+                HttpResponse::new(self.version, HttpStatus::INTERNAL_SERVER_ERROR, None, None)
+                    .send(write_stream)?;
+
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
             }
         };
 
-        response
+        write_stream.write_all(head.as_bytes())?;
+
+        match framing {
+            _ if self.head_only => {}
+            Framing::NoBody => {}
+            Framing::Length(_) | Framing::Close => match self.body {
+                Body::File(file) => {
+                    let length = match framing {
+                        Framing::Length(length) => length,
+                        _ => u64::MAX,
+                    };
+
+                    write_stream.flush()?;
+
+                    return Ok(Some(file.take(length)));
+                }
+                body => body.write_to(write_stream)?,
+            },
+            Framing::Chunked => self.body.write_chunked_to(write_stream)?,
+        }
+
+        write_stream.flush()?;
+
+        Ok(None)
     }
 
-    pub fn send(&self, write_stream: &mut impl Write) -> Result<(), Box<dyn Error>> {
-        let response_str: String = self.into();
+    /// Fit the response to the request it answers
+    ///
+    /// The response is sent in the request's version, and says `Connection: close` when
+    /// the client doesn't expect the connection to persist, or `Connection: keep-alive`
+    /// when an HTTP/1.0 client asked for it. A `HEAD` request gets exactly the head a
+    /// `GET` would, `Content-Length` included, and no body (RFC 9110 §9.3.2).
+    pub fn respond_to(mut self, request: &HttpRequest) -> HttpResponse {
+        if let Version::V1_0 | Version::V1_1 = request.version {
+            self.version = request.version.clone();
+        }
 
-        write!(write_stream, "{}", response_str).unwrap();
+        if !request.keep_alive() {
+            self.headers.insert("Connection", "close");
+        } else if request.version == Version::V1_0 && !self.headers.contains_key("Connection") {
+            self.headers.insert("Connection", "keep-alive");
+        }
 
-        Ok(())
+        self.head_only = request.mothod == Method::Head;
+        self
+    }
+
+    /// Whether the connection can carry another request once this response is sent
+    pub fn keep_alive(&self) -> bool {
+        if self.is_close_delimited() {
+            return false;
+        }
+
+        match self.version {
+            Version::V1_1 => !self.headers.contains_token("Connection", "close"),
+            Version::V1_0 => self.headers.contains_token("Connection", "keep-alive"),
+            _ => false,
+        }
+    }
+
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    /// Whether the connection has to be closed after sending to mark the end of the body
+    pub fn is_close_delimited(&self) -> bool {
+        matches!(self.framing(), Framing::Close)
     }
 
     fn version(&self) -> &Version {
         &self.version
     }
 
-    fn status_code(&self) -> &HttpStatus {
+    pub fn status_code(&self) -> &HttpStatus {
         &self.status_code
     }
 
-    fn headers(&self) -> String {
-        let mut map = self.headers.clone().unwrap();
+    fn framing(&self) -> Framing {
+        let status = self.status_code();
+
+        if status.is_informational() || matches!(status.as_u16(), 204 | 304) {
+            return Framing::NoBody;
+        }
+
+        match (self.body.content_length(), self.version()) {
+            (Some(length), _) => Framing::Length(length),
+            (None, Version::V1_0) => Framing::Close,
+            (None, _) => Framing::Chunked,
+        }
+    }
+
+    /// Status line and header fields, ending with the empty line
+    fn head(&self, framing: &Framing) -> Result<String, HeaderError> {
+        let version: String = self.version().into();
+        let framing_header = match framing {
+            Framing::NoBody => "".into(),
+            Framing::Length(length) => format!("Content-Length: {}\r\n", length),
+            Framing::Chunked => "Transfer-Encoding: chunked\r\n".into(),
+            Framing::Close => "Connection: close\r\n".into(),
+        };
+
+        Ok(format!(
+            "{} {}\r\n{}{}\r\n",
+            version,
+            self.status_code(),
+            self.headers(framing)?,
+            framing_header
+        ))
+    }
+
+    /// Header fields set by the handler and a `Date` unless it set one; framing fields
+    /// are always derived from the body
+    fn headers(&self, framing: &Framing) -> Result<String, HeaderError> {
+        let mut map = self.headers.clone();
         let mut header_str = "".into();
 
-        map.insert("Server".into(), "nginx/1.14.1".into());
+        if !map.contains_key("Date") {
+            map.append("Date", HttpDate::now().to_string());
+        }
+
+        map.remove("Content-Length");
+        map.remove("Transfer-Encoding");
+
+        if let Framing::Close = framing {
+            map.remove("Connection");
+        }
+
+        map.validate()?;
 
         for (k, v) in map.iter() {
             header_str = format!("{}{}: {}\r\n", header_str, k, v);
         }
 
-        header_str
+        Ok(header_str)
+    }
+}
+
+/// Fluent construction of an [`HttpResponse`]
+///
+/// ```
+/// use http::response::{HttpResponse, HttpStatus};
+///
+/// # fn main() -> serde_json::Result<()> {
+/// let response = HttpResponse::builder()
+///     .status(HttpStatus::CREATED)
+///     .header("Location", "/api/shipping/orders/7")
+///     .json(&[7, 8])?
+///     .build();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct ResponseBuilder {
+    response: HttpResponse,
+}
+
+impl ResponseBuilder {
+    pub fn version(mut self, version: Version) -> ResponseBuilder {
+        self.response.version = version;
+        self
+    }
+
+    pub fn status(mut self, status_code: HttpStatus) -> ResponseBuilder {
+        self.response.status_code = status_code;
+        self
+    }
+
+    /// Add a header field, keeping any values already set for `name`
+    pub fn header(
+        mut self,
+        name: impl Into<HeaderName>,
+        value: impl Into<String>,
+    ) -> ResponseBuilder {
+        self.response.headers.append(name, value);
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Body>) -> ResponseBuilder {
+        self.response.body = body.into();
+        self
+    }
+
+    pub fn html(self, body: impl Into<Body>) -> ResponseBuilder {
+        self.content_type("text/html; charset=utf-8").body(body)
+    }
+
+    pub fn text(self, body: impl Into<Body>) -> ResponseBuilder {
+        self.content_type("text/plain; charset=utf-8").body(body)
     }
 
-    fn body(&self) -> &str {
-        if let Some(b) = &self.body {
-            b
-        } else {
-            ""
+    /// Serialize `value` as the json body
+    pub fn json<T: Serialize + ?Sized>(self, value: &T) -> serde_json::Result<ResponseBuilder> {
+        let body = serde_json::to_vec(value)?;
+
+        Ok(self.content_type("application/json").body(body))
+    }
+
+    /// Redirect to `location` with `302 Found`; call [`ResponseBuilder::status`]
+    /// afterwards for another redirect status
+    pub fn redirect(self, location: impl Into<String>) -> ResponseBuilder {
+        let mut builder = self.status(HttpStatus::FOUND);
+
+        builder.response.headers.insert("Location", location);
+        builder
+    }
+
+    /// Stream the file at `path` as the body, typed by its extension
+    pub fn file(self, path: impl AsRef<Path>) -> io::Result<ResponseBuilder> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+
+        if !file.metadata()?.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "not a regular file",
+            ));
         }
+
+        Ok(self.content_type(mime::from_path(path)).body(file))
+    }
+
+    pub fn build(self) -> HttpResponse {
+        self.response
+    }
+
+    fn content_type(mut self, content_type: &str) -> ResponseBuilder {
+        self.response.headers.insert("Content-Type", content_type);
+        self
     }
 }
 
@@ -221,52 +669,407 @@ impl HttpResponse {
 mod response_test {
     use super::*;
 
+    const DATE: &str = "Sat, 24 Sep 2022 03:15:53 GMT";
+
     #[test]
     fn test_http_status_resolve() {
         let status: (u16, String) = (&HttpStatus::OK).into();
-        assert_eq!(status, (200, "Ok".into()));
+        assert_eq!(status, (200, "OK".into()));
 
         let status: (u16, String) = (&HttpStatus::I_AM_A_TEAPOT).into();
-        assert_eq!(status, (418, "I Am A Teapot".into()));
+        assert_eq!(status, (418, "I'm a teapot".into()));
+
+        assert_eq!(HttpStatus::GONE.as_u16(), 410);
+        assert_eq!(HttpStatus::NOT_MODIFIED.reason(), "Not Modified");
+        assert_eq!(HttpStatus::LOOP_DETECTED.to_string(), "508 Loop Detected");
+    }
+
+    #[test]
+    fn test_http_status_convert() {
+        for code in 100..600 {
+            assert_eq!(HttpStatus::try_from(code).unwrap().as_u16(), code);
+        }
+
+        assert_eq!(HttpStatus::try_from(226), Ok(HttpStatus::IM_USED));
+        assert_eq!(
+            HttpStatus::try_from(451),
+            Ok(HttpStatus::UNAVAILABLE_FOR_LEGAL_REASONS)
+        );
+        assert_eq!(
+            HttpStatus::try_from(511),
+            Ok(HttpStatus::NETWORK_AUTHENTICATION_REQUIRED)
+        );
+        assert_eq!(HttpStatus::try_from(299).unwrap().reason(), "");
+        assert_eq!(HttpStatus::try_from(99), Err(InvalidStatus::Code(99)));
+        assert_eq!(HttpStatus::try_from(600), Err(InvalidStatus::Code(600)));
+
+        let status = HttpStatus::custom(299, "Mostly Fine").unwrap();
+
+        assert_eq!(status.to_string(), "299 Mostly Fine");
+        assert!(status.is_success());
+        assert_eq!(
+            HttpStatus::custom(200, "OK\r\nSet-Cookie: a=1"),
+            Err(InvalidStatus::Reason("OK\r\nSet-Cookie: a=1".into()))
+        );
+        assert_eq!(
+            HttpStatus::custom(1000, "Too Big"),
+            Err(InvalidStatus::Code(1000))
+        );
+    }
+
+    #[test]
+    fn test_http_status_class() {
+        assert!(HttpStatus::EARLYHINTS.is_informational());
+        assert!(HttpStatus::IM_USED.is_success());
+        assert!(HttpStatus::PERMANENT_REDIRECT.is_redirect());
+        assert!(HttpStatus::TOO_EARLY.is_client_error());
+        assert!(HttpStatus::INSUFFICIENT_STORAGE.is_server_error());
+        assert!(!HttpStatus::NOT_FOUND.is_server_error());
+    }
+
+    #[test]
+    fn test_parse_error_status() {
+        assert_eq!(
+            HttpStatus::from(&ParseError::BadHeader),
+            HttpStatus::BAD_REQUEST
+        );
+        assert_eq!(
+            HttpStatus::from(&ParseError::AmbiguousFraming),
+            HttpStatus::BAD_REQUEST
+        );
+        assert_eq!(
+            HttpStatus::from(&ParseError::UriTooLong),
+            HttpStatus::URI_TOO_LONG
+        );
+        assert_eq!(
+            HttpStatus::from(&ParseError::HeadersTooLarge),
+            HttpStatus::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+        assert_eq!(
+            HttpStatus::from(&ParseError::UnsupportedVersion),
+            HttpStatus::HTTP_VERSION_NOT_SUPPORTED
+        );
     }
 
     #[test]
     fn test_response_parse() {
-        let response_with_no_headers: String =
-            (&HttpResponse::new(Version::V1_1, HttpStatus::OK, None, None)).into();
+        let response_with_no_headers =
+            to_bytes(HttpResponse::new(Version::V1_1, HttpStatus::OK, None, None));
 
         assert_eq!(
-            String::from("HTTP/1.1 200 Ok\r\nContent-Type: text/html\r\nContent-Length: 0\r\n\r\n"),
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nDate: Sat, 24 Sep 2022 03:15:53 GMT\r\nContent-Length: 0\r\n\r\n".as_bytes().to_vec(),
             response_with_no_headers
         );
 
-        let response_with_body: String = (&HttpResponse::new(
+        let response_with_body = to_bytes(HttpResponse::new(
             Version::V1_1,
             HttpStatus::OK,
             None,
             Some("Hello I Am zRain".into()),
-        ))
-            .into();
-
-        assert_eq!(String::from("HTTP/1.1 200 Ok\r\nContent-Type: text/html\r\nContent-Length: 16\r\n\r\nHello I Am zRain"), response_with_body);
+        ));
 
-        let mut test_header_map: HashMap<String, String> = HashMap::new();
-        let mut header_str: String = "".into();
+        assert_eq!("HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nDate: Sat, 24 Sep 2022 03:15:53 GMT\r\nContent-Length: 16\r\n\r\nHello I Am zRain".as_bytes().to_vec(), response_with_body);
 
-        test_header_map.insert("Server".into(), "nginx/1.14.1".into());
-        test_header_map.insert("Date".into(), "Sat, 24 Sep 2022 03:15:53 GMT".into());
-        test_header_map.insert("Connection".into(), "keep-alive".into());
+        let mut test_header_map = HeaderMap::new();
 
-        for (k, v) in test_header_map.iter() {
-            header_str = format!("{}{}: {}\r\n", header_str, k, v);
-        }
+        test_header_map.insert("Server", "nginx/1.14.1");
+        test_header_map.insert("Date", "Sat, 24 Sep 2022 03:15:53 GMT");
+        test_header_map.insert("Connection", "keep-alive");
+        test_header_map.append("Set-Cookie", "a=1");
+        test_header_map.append("Set-Cookie", "b=2");
 
-        let response_width_headers: String =
-            (&HttpResponse::new(Version::V1_1, HttpStatus::OK, Some(test_header_map), None)).into();
+        let response_width_headers = to_bytes(HttpResponse::new(
+            Version::V1_1,
+            HttpStatus::OK,
+            Some(test_header_map),
+            None,
+        ));
 
         assert_eq!(
-            format!("HTTP/1.1 200 Ok\r\n{}Content-Length: 0\r\n\r\n", header_str),
+            "HTTP/1.1 200 OK\r\nServer: nginx/1.14.1\r\nDate: Sat, 24 Sep 2022 03:15:53 GMT\r\nConnection: keep-alive\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\nContent-Length: 0\r\n\r\n".as_bytes().to_vec(),
             response_width_headers
         );
     }
+
+    #[test]
+    fn test_response_binary_body() {
+        let png = vec![0x89, b'P', b'N', b'G', 0x00, 0xff];
+        let mut headers = HeaderMap::new();
+
+        headers.insert("Content-Type", "image/png");
+
+        let response = to_bytes(HttpResponse::new(
+            Version::V1_1,
+            HttpStatus::OK,
+            Some(headers),
+            Some(png.clone()),
+        ));
+
+        assert!(response.starts_with(b"HTTP/1.1 200 OK\r\nContent-Type: image/png\r\n"));
+        assert!(response.ends_with(b"Content-Length: 6\r\n\r\n\x89PNG\x00\xff"));
+    }
+
+    #[test]
+    fn test_response_framing() {
+        let chunks = vec![Ok(b"id,status\n".to_vec()), Ok(b"1,false\n".to_vec())];
+        let response = to_bytes(HttpResponse::new(
+            Version::V1_1,
+            HttpStatus::OK,
+            None,
+            Body::stream(chunks.into_iter()),
+        ));
+
+        assert_eq!(
+            response,
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nDate: Sat, 24 Sep 2022 03:15:53 GMT\r\nTransfer-Encoding: chunked\r\n\r\na\r\nid,status\n\r\n8\r\n1,false\n\r\n0\r\n\r\n"
+        );
+
+        let response = HttpResponse::new(
+            Version::V1_0,
+            HttpStatus::OK,
+            None,
+            Body::reader(&b"streamed"[..]),
+        );
+
+        assert!(response.is_close_delimited());
+        assert_eq!(
+            to_bytes(response),
+            b"HTTP/1.0 200 OK\r\nContent-Type: text/html\r\nDate: Sat, 24 Sep 2022 03:15:53 GMT\r\nConnection: close\r\n\r\nstreamed"
+        );
+
+        let mut headers = HeaderMap::new();
+
+        headers.insert("Content-Length", "999");
+
+        assert_eq!(
+            to_bytes(HttpResponse::new(
+                Version::V1_1,
+                HttpStatus::NO_CONTENT,
+                Some(headers),
+                "ignored"
+            )),
+            b"HTTP/1.1 204 No Content\r\nDate: Sat, 24 Sep 2022 03:15:53 GMT\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_response_header_injection() {
+        let mut headers = HeaderMap::new();
+
+        headers.insert("Location", "/next\r\nSet-Cookie: session=stolen");
+
+        let response = HttpResponse::new(Version::V1_1, HttpStatus::FOUND, Some(headers), "moved");
+        let mut bytes = Vec::new();
+
+        assert!(response.send(&mut bytes).is_err());
+        assert!(bytes.starts_with(b"HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(bytes.ends_with(b"Content-Length: 0\r\n\r\n"));
+        assert!(!bytes.windows(10).any(|w| w == b"Set-Cookie"));
+
+        let mut headers = HeaderMap::new();
+
+        headers.insert("X-Bad Name", "value");
+
+        let response = HttpResponse::new(Version::V1_1, HttpStatus::OK, Some(headers), None);
+
+        assert!(response.send(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_response_builder() {
+        let response = HttpResponse::builder()
+            .status(HttpStatus::CREATED)
+            .header("Set-Cookie", "a=1")
+            .header("Set-Cookie", "b=2")
+            .json(&vec!["shipped", "pending"])
+            .unwrap()
+            .build();
+
+        assert_eq!(
+            to_bytes(response),
+            b"HTTP/1.1 201 Created\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\nContent-Type: application/json\r\nDate: Sat, 24 Sep 2022 03:15:53 GMT\r\nContent-Length: 21\r\n\r\n[\"shipped\",\"pending\"]"
+        );
+
+        let response = HttpResponse::builder().text("hello").build();
+
+        assert!(to_bytes(response).ends_with(
+            b"Content-Type: text/plain; charset=utf-8\r\nDate: Sat, 24 Sep 2022 03:15:53 GMT\r\nContent-Length: 5\r\n\r\nhello"
+        ));
+
+        let response = HttpResponse::builder()
+            .redirect("/login")
+            .status(HttpStatus::SEE_OTHER)
+            .build();
+
+        assert_eq!(response.status_code(), &HttpStatus::SEE_OTHER);
+        assert_eq!(
+            to_bytes(response),
+            b"HTTP/1.1 303 See Other\r\nLocation: /login\r\nDate: Sat, 24 Sep 2022 03:15:53 GMT\r\nContent-Length: 0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_response_builder_file() {
+        let path = std::env::temp_dir().join(format!("http-builder-{}.txt", std::process::id()));
+
+        std::fs::write(&path, "from disk").unwrap();
+
+        let response = HttpResponse::builder().file(&path).unwrap().build();
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(to_bytes(response).ends_with(
+            b"Content-Type: text/plain\r\nDate: Sat, 24 Sep 2022 03:15:53 GMT\r\nContent-Length: 9\r\n\r\nfrom disk"
+        ));
+        assert!(HttpResponse::builder().file(&path).is_err());
+        assert!(HttpResponse::builder().file(std::env::temp_dir()).is_err());
+    }
+
+    #[test]
+    fn test_response_send_head() {
+        let path = std::env::temp_dir().join(format!("http-send-head-{}.txt", std::process::id()));
+
+        std::fs::write(&path, "from disk").unwrap();
+
+        let mut response = HttpResponse::builder().file(&path).unwrap().build();
+        let mut head = Vec::new();
+
+        response.headers_mut().append("Date", DATE);
+
+        let mut file = response.send_head(&mut head).unwrap().unwrap();
+        let mut body = String::new();
+
+        std::fs::remove_file(&path).unwrap();
+        io::Read::read_to_string(&mut file, &mut body).unwrap();
+
+        assert!(head.ends_with(b"Content-Length: 9\r\n\r\n"));
+        assert_eq!(body, "from disk");
+
+        // Bytes appended after the head was written are not sent
+        std::fs::write(&path, "from disk").unwrap();
+
+        let response = HttpResponse::builder().file(&path).unwrap().build();
+        let mut file = response.send_head(&mut Vec::new()).unwrap().unwrap();
+
+        std::fs::write(&path, "from disk, and more").unwrap();
+        body.clear();
+        io::Read::read_to_string(&mut file, &mut body).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(body, "from disk");
+
+        let mut bytes = Vec::new();
+        let response = HttpResponse::builder().text("in memory").build();
+
+        assert!(response.send_head(&mut bytes).unwrap().is_none());
+        assert!(bytes.ends_with(b"\r\n\r\nin memory"));
+    }
+
+    #[test]
+    fn test_response_date() {
+        let bytes = {
+            let mut bytes = Vec::new();
+
+            HttpResponse::builder().build().send(&mut bytes).unwrap();
+
+            String::from_utf8(bytes).unwrap()
+        };
+        let date = bytes
+            .lines()
+            .find_map(|line| line.strip_prefix("Date: "))
+            .unwrap();
+        let date: HttpDate = date.parse().unwrap();
+
+        assert!(date <= HttpDate::now());
+        assert!(!bytes.contains("Server:"));
+    }
+
+    #[test]
+    fn test_response_to_head() {
+        let head = HttpRequest::try_from(&b"HEAD / HTTP/1.1\r\nHost: a\r\n\r\n"[..]).unwrap();
+        let get = HttpRequest::try_from(&b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"[..]).unwrap();
+        let response = || HttpResponse::builder().html("<h1>hello</h1>");
+
+        assert_eq!(
+            to_bytes(response().build().respond_to(&head)),
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nDate: Sat, 24 Sep 2022 03:15:53 GMT\r\nContent-Length: 14\r\n\r\n"
+        );
+        assert!(to_bytes(response().build().respond_to(&get)).ends_with(b"\r\n\r\n<h1>hello</h1>"));
+
+        let chunks = vec![Ok(b"never sent".to_vec())];
+        let response = HttpResponse::builder()
+            .body(Body::stream(chunks.into_iter()))
+            .build()
+            .respond_to(&head);
+
+        assert!(to_bytes(response).ends_with(b"Transfer-Encoding: chunked\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_response_connection() {
+        let cases = [
+            (
+                "GET / HTTP/1.1\r\nHost: a\r\n\r\n",
+                "HTTP/1.1 200 OK\r\n",
+                None,
+                true,
+            ),
+            (
+                "GET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n",
+                "HTTP/1.1 200 OK\r\n",
+                Some("close"),
+                false,
+            ),
+            (
+                "GET / HTTP/1.0\r\n\r\n",
+                "HTTP/1.0 200 OK\r\n",
+                Some("close"),
+                false,
+            ),
+            (
+                "GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n",
+                "HTTP/1.0 200 OK\r\n",
+                Some("keep-alive"),
+                true,
+            ),
+        ];
+
+        for (raw, status_line, connection, keep_alive) in cases {
+            let request = HttpRequest::try_from(raw.as_bytes()).unwrap();
+            let response = HttpResponse::builder()
+                .text("hi")
+                .build()
+                .respond_to(&request);
+
+            assert_eq!(response.headers.get("Connection"), connection, "{}", raw);
+            assert_eq!(response.keep_alive(), keep_alive, "{}", raw);
+            assert!(to_bytes(response).starts_with(status_line.as_bytes()));
+        }
+
+        let request =
+            HttpRequest::try_from(&b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n"[..])
+                .unwrap();
+        let response = HttpResponse::builder()
+            .body(Body::reader(&b"unknown length"[..]))
+            .build()
+            .respond_to(&request);
+
+        assert!(!response.keep_alive());
+        assert!(String::from_utf8(to_bytes(response))
+            .unwrap()
+            .contains("\r\nConnection: close\r\n"));
+    }
+
+    /// Serialize with a fixed `Date`, so the output can be compared byte for byte
+    fn to_bytes(mut response: HttpResponse) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        if !response.headers_mut().contains_key("Date") {
+            response.headers_mut().append("Date", DATE);
+        }
+
+        response.send(&mut bytes).unwrap();
+
+        bytes
+    }
 }
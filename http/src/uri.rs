@@ -0,0 +1,231 @@
+use std::fmt;
+
+use crate::request::ParseError;
+
+/// A percent-decoded, normalized request path
+///
+/// It always starts with `/` and never contains empty, `.` or `..` segments, so joining it
+/// onto a directory can't escape that directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UriPath(String);
+
+impl UriPath {
+    /// Decode and normalize a raw path following RFC 3986 §5.2.4
+    ///
+    /// Segments are decoded before dot segments are resolved, so `%2e%2e` counts as `..`.
+    /// Encoded NULs, slashes and backslashes are rejected because they would change
+    /// the meaning of the path once decoded.
+    pub fn parse(raw: &str) -> Result<UriPath, ParseError> {
+        let raw = raw.strip_prefix('/').ok_or(ParseError::BadTarget)?;
+        let mut segments: Vec<String> = Vec::new();
+        let mut trailing_slash = false;
+
+        for segment in raw.split('/') {
+            let decoded =
+                String::from_utf8(percent_decode(segment)?).map_err(|_| ParseError::BadTarget)?;
+
+            if decoded.contains(['\0', '/', '\\']) {
+                return Err(ParseError::BadTarget);
+            }
+
+            trailing_slash = true;
+
+            match decoded.as_str() {
+                "" | "." => {}
+                ".." => {
+                    segments.pop();
+                }
+                _ => {
+                    segments.push(decoded);
+                    trailing_slash = false;
+                }
+            }
+        }
+
+        let mut path = format!("/{}", segments.join("/"));
+
+        if trailing_slash && !segments.is_empty() {
+            path.push('/');
+        }
+
+        Ok(UriPath(path))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Non-empty path segments, e.g. `["api", "orders"]` for `/api/orders`
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split('/').filter(|s| !s.is_empty())
+    }
+}
+
+impl Default for UriPath {
+    fn default() -> UriPath {
+        UriPath("/".into())
+    }
+}
+
+impl fmt::Display for UriPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Decoded query parameters
+///
+/// A key may appear more than once (`?tag=a&tag=b`), so pairs are kept in the order they
+/// were received instead of being folded into a map.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Query {
+    pairs: Vec<(String, String)>,
+}
+
+impl Query {
+    /// Parse an `application/x-www-form-urlencoded` query, without the leading `?`
+    pub fn parse(raw: &str) -> Result<Query, ParseError> {
+        let mut pairs = Vec::new();
+
+        for pair in raw.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+
+            pairs.push((decode_component(key)?, decode_component(value)?));
+        }
+
+        Ok(Query { pairs })
+    }
+
+    /// First value of `key`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Every value of `key`, in request order
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> {
+        self.pairs
+            .iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.pairs.iter().any(|(k, _)| k == key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+/// Decode `%XX` escapes; malformed escapes are rejected rather than passed through
+pub fn percent_decode(input: &str) -> Result<Vec<u8>, ParseError> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'%' => {
+                let hex = bytes.get(idx + 1..idx + 3).ok_or(ParseError::BadTarget)?;
+                let high = hex_value(hex[0]).ok_or(ParseError::BadTarget)?;
+                let low = hex_value(hex[1]).ok_or(ParseError::BadTarget)?;
+
+                decoded.push(high << 4 | low);
+                idx += 3;
+            }
+            b => {
+                decoded.push(b);
+                idx += 1;
+            }
+        }
+    }
+
+    Ok(decoded)
+}
+
+/// Decode a query key or value, where `+` stands for a space
+fn decode_component(input: &str) -> Result<String, ParseError> {
+    let decoded = percent_decode(&input.replace('+', " "))?;
+
+    String::from_utf8(decoded).map_err(|_| ParseError::BadTarget)
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod uri_test {
+    use super::*;
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("/a%20b%2Fc").unwrap(), b"/a b/c");
+        assert_eq!(percent_decode("%e4%bd%a0").unwrap(), "你".as_bytes());
+        assert_eq!(percent_decode("100%"), Err(ParseError::BadTarget));
+        assert_eq!(percent_decode("%zz"), Err(ParseError::BadTarget));
+    }
+
+    #[test]
+    fn test_path_normalize() {
+        let cases = [
+            ("/", "/"),
+            ("/index.html", "/index.html"),
+            ("//a///b", "/a/b"),
+            ("/a/./b/../c", "/a/c"),
+            ("/a/b/..", "/a/"),
+            ("/a/b/", "/a/b/"),
+            ("/../../etc/passwd", "/etc/passwd"),
+            ("/%2e%2e/%2E%2E/etc/passwd", "/etc/passwd"),
+            ("/hello%20world", "/hello world"),
+        ];
+
+        for (raw, normalized) in cases {
+            assert_eq!(UriPath::parse(raw).unwrap().as_str(), normalized, "{}", raw);
+        }
+
+        assert_eq!(UriPath::parse("/a%00.html"), Err(ParseError::BadTarget));
+        assert_eq!(UriPath::parse("/..%2f..%2fetc"), Err(ParseError::BadTarget));
+        assert_eq!(UriPath::parse("/..%5c..%5cetc"), Err(ParseError::BadTarget));
+        assert_eq!(UriPath::parse("/%ff"), Err(ParseError::BadTarget));
+        assert_eq!(UriPath::parse("relative"), Err(ParseError::BadTarget));
+        assert_eq!(
+            UriPath::parse("/api//orders/")
+                .unwrap()
+                .segments()
+                .collect::<Vec<_>>(),
+            vec!["api", "orders"]
+        );
+    }
+
+    #[test]
+    fn test_query_parse() {
+        let query = Query::parse("status=true&page=2&tag=a&tag=b+c&flag&&name=%E5%A5%BD").unwrap();
+
+        assert_eq!(query.get("status"), Some("true"));
+        assert_eq!(query.get("page"), Some("2"));
+        assert_eq!(query.get_all("tag").collect::<Vec<_>>(), vec!["a", "b c"]);
+        assert_eq!(query.get("flag"), Some(""));
+        assert_eq!(query.get("name"), Some("好"));
+        assert_eq!(query.get("missing"), None);
+        assert_eq!(query.len(), 6);
+        assert_eq!(Query::parse("bad=%ff"), Err(ParseError::BadTarget));
+    }
+}
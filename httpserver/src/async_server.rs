@@ -0,0 +1,227 @@
+use std::io;
+use std::net;
+use std::sync::Arc;
+
+use futures_util::future::{self, Either};
+use futures_util::{SinkExt, StreamExt};
+use http::codec::{CodecError, HttpCodec};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime;
+use tokio::sync::watch;
+use tokio::task::{self, JoinSet};
+use tokio::time;
+use tokio_util::codec::Framed;
+
+use crate::log;
+use crate::server::{Server, ServerConfig};
+use crate::shutdown::Shutdown;
+
+/// Serve the listener from a Tokio runtime with `config.workers` threads, one task per
+/// connection, until the tasks have drained after shutdown
+pub fn run(
+    listener: net::TcpListener,
+    config: &Arc<ServerConfig>,
+    shutdown: &Shutdown,
+) -> io::Result<()> {
+    let runtime = runtime::Builder::new_multi_thread()
+        .worker_threads(config.workers)
+        .enable_io()
+        .enable_time()
+        .build()?;
+
+    // Unlike a notification, the watched flag can't be missed by a task that starts
+    // waiting after shutdown was requested
+    let (stop, stopping) = watch::channel(false);
+
+    shutdown.on_shutdown(move || {
+        stop.send_replace(true);
+    });
+    listener.set_nonblocking(true)?;
+
+    runtime.block_on(async {
+        let listener = TcpListener::from_std(listener)?;
+        let mut tasks = JoinSet::new();
+        let mut stopped = stopping.clone();
+
+        loop {
+            let accept = listener.accept();
+            let stop = stopped.wait_for(|stop| *stop);
+            let accepted = match future::select(Box::pin(accept), Box::pin(stop)).await {
+                Either::Left((accepted, _)) => accepted,
+                Either::Right(_) => break,
+            };
+            let stream = match accepted {
+                Ok((stream, _)) => stream,
+                // e.g. out of file descriptors, which shouldn't stop the server
+                Err(e) => {
+                    log::error!("Accept connection error: {}", e);
+                    continue;
+                }
+            };
+
+            // Reap finished tasks, so the set only holds open connections
+            while tasks.try_join_next().is_some() {}
+
+            tasks.spawn(handle_connection(
+                Arc::clone(config),
+                shutdown.clone(),
+                stopping.clone(),
+                stream,
+            ));
+        }
+
+        drop(listener);
+
+        let deadline = shutdown.deadline(config.grace_period);
+        let deadline = time::Instant::from_std(deadline.expect("shutdown was requested"));
+
+        // Tasks still running are aborted when the set is dropped
+        let drained = time::timeout_at(deadline, async {
+            while tasks.join_next().await.is_some() {}
+        });
+
+        if drained.await.is_err() {
+            log::error!(
+                "Dropping {} connections after the grace period",
+                tasks.len()
+            );
+        }
+
+        Ok(())
+    })
+}
+
+/// Serve requests from one connection, in order, until either side closes it
+///
+/// Routing and encoding run on the blocking pool, as handlers read files and file bodies
+/// are read whole, and the encoded response is then written from the task.
+///
+/// The connection may sit idle for `keep_alive_timeout` between requests, or until
+/// shutdown is requested. Once it turns readable the whole request has to arrive within
+/// `request_timeout`, and the whole response has to be sent within `write_timeout`.
+async fn handle_connection(
+    config: Arc<ServerConfig>,
+    shutdown: Shutdown,
+    mut stopping: watch::Receiver<bool>,
+    stream: TcpStream,
+) {
+    let codec = HttpCodec::with_config(config.parser.clone());
+    let mut framed = Framed::new(stream, codec);
+
+    for served in 1.. {
+        // Pipelined bytes already buffered count as the start of the next request
+        if framed.read_buffer().is_empty() {
+            let readable = time::timeout(config.keep_alive_timeout, framed.get_ref().readable());
+
+            let stop = stopping.wait_for(|stop| *stop);
+
+            match future::select(Box::pin(readable), Box::pin(stop)).await {
+                Either::Left((Ok(Ok(())), _)) => {}
+                // The idle timeout ran out, the socket failed, or shutdown was requested
+                _ => return,
+            }
+        }
+
+        let request = match time::timeout(config.request_timeout, framed.next()).await {
+            Err(_) => Err(Server::time_out(&config)),
+            // The client closed the connection
+            Ok(None) => return,
+            Ok(Some(Ok(request))) => Ok(request),
+            Ok(Some(Err(CodecError::Parse(e)))) => Err(Server::reject(&config, &e)),
+            Ok(Some(Err(CodecError::Io(e)))) => {
+                log::error!("Read request error: {}", e);
+                return;
+            }
+        };
+        let encoded = {
+            let config = Arc::clone(&config);
+            let shutdown = shutdown.clone();
+
+            task::spawn_blocking(move || {
+                let response = match request {
+                    Ok(request) => Server::respond(&config, &request, served, &shutdown),
+                    Err(response) => response,
+                };
+                let keep_alive = response.keep_alive();
+                let mut bytes = Vec::new();
+
+                // An invalid response is replaced by a 500, which still has to be sent
+                let sent = response.send(&mut bytes);
+
+                (bytes, sent.is_ok() && keep_alive, sent)
+            })
+        };
+        let (bytes, keep_alive) = match encoded.await {
+            Ok((bytes, keep_alive, sent)) => {
+                if let Err(e) = sent {
+                    log::error!("Send response error: {}", e);
+                }
+
+                (bytes, keep_alive)
+            }
+            Err(e) => {
+                log::error!("Send response error: {}", e);
+                return;
+            }
+        };
+
+        framed.write_buffer_mut().extend_from_slice(&bytes);
+
+        match time::timeout(config.write_timeout, framed.flush()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                log::error!("Send response error: {}", e);
+                return;
+            }
+            Err(_) => {
+                log::error!("Send response error: timed out");
+                return;
+            }
+        }
+
+        if !keep_alive {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod async_server_test {
+    use super::*;
+    use crate::testing::{self, TestServer};
+    use std::fs;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_pipelined() {
+        let config = testing::config();
+        let health = fs::read(config.public_dir.join("health.html")).unwrap();
+        let index = fs::read(config.public_dir.join("index.html")).unwrap();
+        let server = TestServer::start(config, |listener, config, shutdown| {
+            run(listener, config, shutdown)
+        });
+        let mut stream = TcpStream::connect(server.addr).await.unwrap();
+        let mut received = Vec::new();
+
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: a\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        time::timeout(Duration::from_secs(5), stream.read_to_end(&mut received))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let received = String::from_utf8(received).unwrap();
+        let (first, second) = received.split_at(received.rfind("HTTP/1.1 ").unwrap());
+
+        assert!(first.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(first.ends_with(std::str::from_utf8(&health).unwrap()));
+        assert!(second.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(second.contains("\r\nConnection: close\r\n"));
+        assert!(second.ends_with(std::str::from_utf8(&index).unwrap()));
+
+        server.stop();
+    }
+}
@@ -0,0 +1,547 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::Parser;
+use serde::Deserialize;
+
+use crate::log::LogLevel;
+use crate::server::{Backend, ServerConfig};
+
+/// Serve the demo site and its shipping API
+///
+/// Every setting can also be given in a TOML file or an environment variable. Flags win
+/// over environment variables, which win over the file, which wins over the defaults.
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Cli {
+    /// TOML file to read settings from [env: SERVER_CONFIG]
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// Check the configuration and exit instead of serving
+    #[arg(long)]
+    pub check: bool,
+    /// Address to listen on, repeat it or separate addresses with commas for several
+    /// [env: SERVER_BIND]
+    #[arg(short, long, value_delimiter = ',')]
+    pub bind: Vec<String>,
+    /// Directory static pages are served from [env: PUBLIC_PATH]
+    #[arg(long)]
+    pub public_dir: Option<PathBuf>,
+    /// Directory of the data behind /api [env: DATA_PATH]
+    #[arg(long)]
+    pub data_dir: Option<PathBuf>,
+    /// threaded, event-loop, or io-uring and async when built with those features
+    /// [env: SERVER_BACKEND]
+    #[arg(long)]
+    pub backend: Option<Backend>,
+    /// Threads serving connections on each address [env: SERVER_WORKERS]
+    #[arg(short, long)]
+    pub workers: Option<usize>,
+    /// Connections waiting for a thread of the threaded backend before new ones get a 503
+    /// [env: SERVER_QUEUE_SIZE]
+    #[arg(long)]
+    pub queue_size: Option<usize>,
+    /// Value of the Server header, empty to leave it out [env: SERVER_HEADER]
+    #[arg(long)]
+    pub server_header: Option<String>,
+    /// Seconds a kept-alive connection may wait for its next request
+    /// [env: SERVER_KEEP_ALIVE_TIMEOUT]
+    #[arg(long)]
+    pub keep_alive_timeout: Option<u64>,
+    /// Seconds a client may take to send a whole request [env: SERVER_REQUEST_TIMEOUT]
+    #[arg(long)]
+    pub request_timeout: Option<u64>,
+    /// Seconds sending a response may stall [env: SERVER_WRITE_TIMEOUT]
+    #[arg(long)]
+    pub write_timeout: Option<u64>,
+    /// Seconds requests in flight may take to finish after SIGINT or SIGTERM
+    /// [env: SERVER_GRACE_PERIOD]
+    #[arg(long)]
+    pub grace_period: Option<u64>,
+    /// Requests served on one connection before it is closed [env: SERVER_MAX_REQUESTS]
+    #[arg(long)]
+    pub max_requests: Option<usize>,
+    /// Bytes of a request line [env: SERVER_MAX_REQUEST_LINE]
+    #[arg(long)]
+    pub max_request_line: Option<usize>,
+    /// Bytes of a header line [env: SERVER_MAX_HEADER_LINE]
+    #[arg(long)]
+    pub max_header_line: Option<usize>,
+    /// Bytes of a request line and headers [env: SERVER_MAX_HEAD]
+    #[arg(long)]
+    pub max_head: Option<usize>,
+    /// Header fields of a request [env: SERVER_MAX_HEADERS]
+    #[arg(long)]
+    pub max_headers: Option<usize>,
+    /// Bytes of a request body [env: SERVER_MAX_BODY]
+    #[arg(long)]
+    pub max_body: Option<usize>,
+    /// off, error, info or debug [env: SERVER_LOG]
+    #[arg(long)]
+    pub log_level: Option<LogLevel>,
+}
+
+/// Settings read from a TOML file, all of them optional
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    bind: Option<Vec<String>>,
+    public_dir: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+    backend: Option<String>,
+    workers: Option<usize>,
+    queue_size: Option<usize>,
+    server_header: Option<String>,
+    timeouts: Timeouts,
+    limits: Limits,
+    log: Log,
+}
+
+/// Timeouts in seconds
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct Timeouts {
+    keep_alive: Option<u64>,
+    request: Option<u64>,
+    write: Option<u64>,
+    grace_period: Option<u64>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct Limits {
+    max_requests: Option<usize>,
+    max_request_line: Option<usize>,
+    max_header_line: Option<usize>,
+    max_headers: Option<usize>,
+    max_head: Option<usize>,
+    max_body: Option<usize>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct Log {
+    level: Option<String>,
+}
+
+/// Why the configuration can't be used
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    /// A setting is out of range or names something that doesn't exist
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Build the server configuration from the defaults, the file named on the command line
+/// and the overrides given as flags or environment variables, then validate it
+///
+/// Environment variables are looked up with `env`, rather than read from the process, so
+/// that the caller decides which environment applies. Relative directories in the file
+/// are taken from the file's directory, the ones given as flags or environment variables
+/// from the working directory.
+pub fn load(
+    mut cli: Cli,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<ServerConfig, ConfigError> {
+    let mut config = ServerConfig::default();
+
+    apply_env(&mut cli, env)?;
+
+    if let Some(path) = &cli.config {
+        let content = fs::read_to_string(path).map_err(|e| ConfigError::Read(path.clone(), e))?;
+        let file: FileConfig =
+            toml::from_str(&content).map_err(|e| ConfigError::Parse(path.clone(), e))?;
+
+        apply_file(&mut config, file, path.parent().unwrap_or(Path::new("")))?;
+    }
+
+    apply_cli(&mut config, cli);
+    validate(&config)?;
+
+    Ok(config)
+}
+
+/// Fill in the flags left out from their environment variables
+fn apply_env(cli: &mut Cli, env: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+    if cli.bind.is_empty() {
+        if let Some(bind) = env("SERVER_BIND") {
+            cli.bind = bind.split(',').map(String::from).collect();
+        }
+    }
+
+    set_from_env(&mut cli.config, "SERVER_CONFIG", &env)?;
+    set_from_env(&mut cli.public_dir, "PUBLIC_PATH", &env)?;
+    set_from_env(&mut cli.data_dir, "DATA_PATH", &env)?;
+    set_from_env(&mut cli.backend, "SERVER_BACKEND", &env)?;
+    set_from_env(&mut cli.workers, "SERVER_WORKERS", &env)?;
+    set_from_env(&mut cli.queue_size, "SERVER_QUEUE_SIZE", &env)?;
+    set_from_env(&mut cli.server_header, "SERVER_HEADER", &env)?;
+    set_from_env(
+        &mut cli.keep_alive_timeout,
+        "SERVER_KEEP_ALIVE_TIMEOUT",
+        &env,
+    )?;
+    set_from_env(&mut cli.request_timeout, "SERVER_REQUEST_TIMEOUT", &env)?;
+    set_from_env(&mut cli.write_timeout, "SERVER_WRITE_TIMEOUT", &env)?;
+    set_from_env(&mut cli.grace_period, "SERVER_GRACE_PERIOD", &env)?;
+    set_from_env(&mut cli.max_requests, "SERVER_MAX_REQUESTS", &env)?;
+    set_from_env(&mut cli.max_request_line, "SERVER_MAX_REQUEST_LINE", &env)?;
+    set_from_env(&mut cli.max_header_line, "SERVER_MAX_HEADER_LINE", &env)?;
+    set_from_env(&mut cli.max_head, "SERVER_MAX_HEAD", &env)?;
+    set_from_env(&mut cli.max_headers, "SERVER_MAX_HEADERS", &env)?;
+    set_from_env(&mut cli.max_body, "SERVER_MAX_BODY", &env)?;
+    set_from_env(&mut cli.log_level, "SERVER_LOG", &env)
+}
+
+fn set_from_env<T>(
+    flag: &mut Option<T>,
+    var: &str,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<(), ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    if flag.is_some() {
+        return Ok(());
+    }
+
+    if let Some(value) = env(var) {
+        let value = value
+            .parse()
+            .map_err(|e| ConfigError::Invalid(format!("{}: {}", var, e)))?;
+
+        *flag = Some(value);
+    }
+
+    Ok(())
+}
+
+fn apply_file(config: &mut ServerConfig, file: FileConfig, base: &Path) -> Result<(), ConfigError> {
+    let invalid = |key: &str, e: String| ConfigError::Invalid(format!("{}: {}", key, e));
+
+    if let Some(bind) = file.bind {
+        config.bind = bind;
+    }
+    if let Some(public_dir) = file.public_dir {
+        config.public_dir = base.join(public_dir);
+    }
+    if let Some(data_dir) = file.data_dir {
+        config.data_dir = base.join(data_dir);
+    }
+    if let Some(backend) = file.backend {
+        config.backend = backend.parse().map_err(|e| invalid("backend", e))?;
+    }
+    if let Some(workers) = file.workers {
+        config.workers = workers;
+    }
+    if let Some(queue_size) = file.queue_size {
+        config.queue_size = queue_size;
+    }
+    if let Some(server_header) = file.server_header {
+        config.server_header = Some(server_header).filter(|h| !h.is_empty());
+    }
+    if let Some(level) = file.log.level {
+        config.log_level = level.parse().map_err(|e| invalid("log.level", e))?;
+    }
+
+    let timeouts = file.timeouts;
+
+    set_secs(&mut config.keep_alive_timeout, timeouts.keep_alive);
+    set_secs(&mut config.request_timeout, timeouts.request);
+    set_secs(&mut config.write_timeout, timeouts.write);
+    set_secs(&mut config.grace_period, timeouts.grace_period);
+
+    let limits = file.limits;
+    let parser = &mut config.parser;
+
+    config.max_requests = limits.max_requests.unwrap_or(config.max_requests);
+    parser.max_request_line = limits.max_request_line.unwrap_or(parser.max_request_line);
+    parser.max_header_line = limits.max_header_line.unwrap_or(parser.max_header_line);
+    parser.max_headers = limits.max_headers.unwrap_or(parser.max_headers);
+    parser.max_head = limits.max_head.unwrap_or(parser.max_head);
+    parser.max_body = limits.max_body.unwrap_or(parser.max_body);
+
+    Ok(())
+}
+
+fn apply_cli(config: &mut ServerConfig, cli: Cli) {
+    if !cli.bind.is_empty() {
+        config.bind = cli.bind;
+    }
+    if let Some(public_dir) = cli.public_dir {
+        config.public_dir = public_dir;
+    }
+    if let Some(data_dir) = cli.data_dir {
+        config.data_dir = data_dir;
+    }
+    if let Some(backend) = cli.backend {
+        config.backend = backend;
+    }
+    if let Some(workers) = cli.workers {
+        config.workers = workers;
+    }
+    if let Some(queue_size) = cli.queue_size {
+        config.queue_size = queue_size;
+    }
+    if let Some(server_header) = cli.server_header {
+        config.server_header = Some(server_header).filter(|h| !h.is_empty());
+    }
+    if let Some(log_level) = cli.log_level {
+        config.log_level = log_level;
+    }
+
+    set_secs(&mut config.keep_alive_timeout, cli.keep_alive_timeout);
+    set_secs(&mut config.request_timeout, cli.request_timeout);
+    set_secs(&mut config.write_timeout, cli.write_timeout);
+    set_secs(&mut config.grace_period, cli.grace_period);
+
+    let parser = &mut config.parser;
+
+    config.max_requests = cli.max_requests.unwrap_or(config.max_requests);
+    parser.max_request_line = cli.max_request_line.unwrap_or(parser.max_request_line);
+    parser.max_header_line = cli.max_header_line.unwrap_or(parser.max_header_line);
+    parser.max_head = cli.max_head.unwrap_or(parser.max_head);
+    parser.max_headers = cli.max_headers.unwrap_or(parser.max_headers);
+    parser.max_body = cli.max_body.unwrap_or(parser.max_body);
+}
+
+fn set_secs(duration: &mut Duration, secs: Option<u64>) {
+    if let Some(secs) = secs {
+        *duration = Duration::from_secs(secs);
+    }
+}
+
+/// Refuse settings the server couldn't run with
+fn validate(config: &ServerConfig) -> Result<(), ConfigError> {
+    let invalid = |message: String| Err(ConfigError::Invalid(message));
+
+    if config.bind.is_empty() {
+        return invalid("no address to bind".into());
+    }
+
+    for (name, dir) in [
+        ("public_dir", &config.public_dir),
+        ("data_dir", &config.data_dir),
+    ] {
+        if !dir.is_dir() {
+            return invalid(format!("{}: {} is not a directory", name, dir.display()));
+        }
+    }
+
+    let timeouts = [
+        ("keep_alive timeout", config.keep_alive_timeout),
+        ("request timeout", config.request_timeout),
+        ("write timeout", config.write_timeout),
+    ];
+
+    // A zero socket timeout would mean no timeout at all
+    for (name, timeout) in timeouts {
+        if timeout.is_zero() {
+            return invalid(format!("{} must be at least a second", name));
+        }
+    }
+
+    let parser = &config.parser;
+    let limits = [
+        ("workers", config.workers),
+        ("max_requests", config.max_requests),
+        ("max_request_line", parser.max_request_line),
+        ("max_header_line", parser.max_header_line),
+        ("max_headers", parser.max_headers),
+        ("max_head", parser.max_head),
+    ];
+
+    for (name, limit) in limits {
+        if limit == 0 {
+            return invalid(format!("{} must be at least 1", name));
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve every address to bind, so that `--check` catches typos without binding
+pub fn check_addresses(config: &ServerConfig) -> Result<(), ConfigError> {
+    for addr in &config.bind {
+        addr.as_str()
+            .to_socket_addrs()
+            .map_err(|e| ConfigError::Invalid(format!("bind {}: {}", addr, e)))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod config_test {
+    use super::*;
+    use std::env;
+
+    fn load_args(args: &[&str]) -> Result<ServerConfig, ConfigError> {
+        load_env(args, &[])
+    }
+
+    /// Load with `vars` as the whole environment
+    fn load_env(args: &[&str], vars: &[(&str, &str)]) -> Result<ServerConfig, ConfigError> {
+        let cli = Cli::try_parse_from(["httpserver"].iter().chain(args)).unwrap();
+
+        load(cli, |var| {
+            vars.iter()
+                .find(|(name, _)| *name == var)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    /// Write a config file into a directory of its own, named after the test
+    fn write_config(name: &str, content: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("httpserver-{}-{}", name, std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("httpserver.toml"), content).unwrap();
+
+        dir.join("httpserver.toml")
+    }
+
+    #[test]
+    fn test_load_precedence() {
+        let path = write_config(
+            "precedence",
+            "workers = 2\nqueue_size = 3\n[limits]\nmax_requests = 4\n",
+        );
+        let path = path.to_str().unwrap();
+
+        let vars = [("SERVER_WORKERS", "5"), ("SERVER_QUEUE_SIZE", "6")];
+        let config = load_env(&["--config", path, "--workers", "7"], &vars).unwrap();
+
+        assert_eq!(config.workers, 7);
+        assert_eq!(config.queue_size, 6);
+        assert_eq!(config.max_requests, 4);
+        assert_eq!(
+            config.parser.max_head,
+            ServerConfig::default().parser.max_head
+        );
+
+        fs::remove_dir_all(Path::new(path).parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_load_env() {
+        let vars = [
+            ("SERVER_BIND", "127.0.0.1:0,[::1]:0"),
+            ("SERVER_BACKEND", "event-loop"),
+        ];
+        let config = load_env(&[], &vars).unwrap();
+
+        assert_eq!(config.bind, ["127.0.0.1:0", "[::1]:0"]);
+        assert_eq!(config.backend, Backend::EventLoop);
+
+        let config = load_env(&["--bind", "localhost:0"], &vars).unwrap();
+
+        assert_eq!(config.bind, ["localhost:0"]);
+        assert!(matches!(
+            load_env(&[], &[("SERVER_WORKERS", "many")]),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_relative_dirs() {
+        let path = write_config("dirs", "public_dir = \"site\"\ndata_dir = \"api\"\n");
+        let dir = path.parent().unwrap().to_path_buf();
+
+        fs::create_dir_all(dir.join("site")).unwrap();
+        fs::create_dir_all(dir.join("api")).unwrap();
+
+        let config = load_args(&["-c", path.to_str().unwrap()]).unwrap();
+
+        assert_eq!(config.public_dir, dir.join("site"));
+        assert_eq!(config.data_dir, dir.join("api"));
+
+        // Tests run from the package directory
+        let config = load_args(&["-c", path.to_str().unwrap(), "--data-dir", "data"]).unwrap();
+
+        assert_eq!(config.data_dir, PathBuf::from("data"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_unknown_fields() {
+        for (name, content) in [
+            ("unknown", "bnd = [\"localhost:3000\"]\n"),
+            ("unknown-nested", "[timeouts]\nkeepalive = 5\n"),
+        ] {
+            let path = write_config(name, content);
+
+            assert!(matches!(
+                load_args(&["-c", path.to_str().unwrap()]),
+                Err(ConfigError::Parse(..))
+            ));
+
+            fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        }
+
+        let path = write_config("bad-backend", "backend = \"fibers\"\n");
+
+        assert!(matches!(
+            load_args(&["-c", path.to_str().unwrap()]),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_load_zero_values() {
+        for flag in [
+            "--workers",
+            "--keep-alive-timeout",
+            "--request-timeout",
+            "--write-timeout",
+            "--max-requests",
+            "--max-request-line",
+            "--max-header-line",
+            "--max-headers",
+            "--max-head",
+        ] {
+            assert!(
+                matches!(load_args(&[flag, "0"]), Err(ConfigError::Invalid(_))),
+                "{}",
+                flag
+            );
+        }
+
+        let path = write_config("zero", "[timeouts]\nwrite = 0\n");
+
+        assert!(matches!(
+            load_args(&["-c", path.to_str().unwrap()]),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(matches!(
+            load_args(&["--public-dir", "missing"]),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(load_args(&["--grace-period", "0", "--queue-size", "0"]).is_ok());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
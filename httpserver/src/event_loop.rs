@@ -0,0 +1,575 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Take, Write};
+use std::net;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use http::request::{ParseStatus, RequestParser};
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Token, Waker};
+
+use crate::log;
+use crate::server::{Server, ServerConfig};
+use crate::shutdown::Shutdown;
+
+const LISTENER: Token = Token(0);
+const WAKER: Token = Token(1);
+
+/// Longest wait for events, which bounds how late idle connections are noticed
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Bytes of a file body read at a time, once the socket has taken the previous ones
+const FILE_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Serve the listener from `config.workers` event loops until they have drained after
+/// shutdown, or one of them fails
+///
+/// Every loop polls its own clone of the listener, so whichever loop wakes up first
+/// accepts a new connection and keeps it for its whole life.
+pub fn run(
+    listener: net::TcpListener,
+    config: &ServerConfig,
+    shutdown: &Shutdown,
+) -> io::Result<()> {
+    listener.set_nonblocking(true)?;
+
+    thread::scope(|scope| {
+        let loops = (0..config.workers)
+            .map(|_| {
+                let listener = TcpListener::from_std(listener.try_clone()?);
+
+                let event_loop = EventLoop::new(listener, config, shutdown.clone())?;
+
+                Ok(scope.spawn(move || event_loop.run()))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        for event_loop in loops {
+            event_loop.join().unwrap()?;
+        }
+
+        Ok(())
+    })
+}
+
+struct EventLoop<'a> {
+    poll: Poll,
+    listener: TcpListener,
+    connections: HashMap<Token, Connection>,
+    next_token: usize,
+    config: &'a ServerConfig,
+    shutdown: Shutdown,
+    /// Shutdown was requested and the listener is no longer polled
+    draining: bool,
+}
+
+impl<'a> EventLoop<'a> {
+    fn new(
+        mut listener: TcpListener,
+        config: &'a ServerConfig,
+        shutdown: Shutdown,
+    ) -> io::Result<EventLoop<'a>> {
+        let poll = Poll::new()?;
+        let waker = Waker::new(poll.registry(), WAKER)?;
+
+        poll.registry()
+            .register(&mut listener, LISTENER, Interest::READABLE)?;
+        shutdown.on_shutdown(move || {
+            let _ = waker.wake();
+        });
+
+        Ok(EventLoop {
+            poll,
+            listener,
+            connections: HashMap::new(),
+            next_token: WAKER.0 + 1,
+            config,
+            shutdown,
+            draining: false,
+        })
+    }
+
+    fn run(mut self) -> io::Result<()> {
+        let mut events = Events::with_capacity(1024);
+
+        loop {
+            match self.poll.poll(&mut events, Some(POLL_TIMEOUT)) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => result?,
+            }
+
+            for event in events.iter() {
+                match event.token() {
+                    LISTENER if !self.draining => self.accept()?,
+                    LISTENER | WAKER => {}
+                    token => self.ready(token),
+                }
+            }
+
+            self.check_timeouts();
+
+            if self.shutdown.is_requested() && self.drain()? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Stop accepting and close the connections waiting idle, returning whether the
+    /// loop is done: no connection is left, or the grace period ran out
+    fn drain(&mut self) -> io::Result<bool> {
+        if !self.draining {
+            self.draining = true;
+            self.poll.registry().deregister(&mut self.listener)?;
+        }
+
+        let deadline = self.shutdown.deadline(self.config.grace_period);
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            if !self.connections.is_empty() {
+                log::error!(
+                    "Dropping {} connections after the grace period",
+                    self.connections.len()
+                );
+            }
+
+            return Ok(true);
+        }
+
+        let idle: Vec<Token> = self
+            .connections
+            .iter()
+            .filter_map(|(token, connection)| connection.is_idle().then_some(*token))
+            .collect();
+
+        for token in idle {
+            self.close(token);
+        }
+
+        Ok(self.connections.is_empty())
+    }
+
+    /// Accept every pending connection
+    fn accept(&mut self) -> io::Result<()> {
+        loop {
+            let mut stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                // e.g. out of file descriptors, which shouldn't stop the loop
+                Err(e) => {
+                    log::error!("Accept connection error: {}", e);
+                    return Ok(());
+                }
+            };
+            let token = Token(self.next_token);
+
+            self.next_token += 1;
+            // Readiness is edge triggered, so both interests can stay registered for good.
+            self.poll.registry().register(
+                &mut stream,
+                token,
+                Interest::READABLE | Interest::WRITABLE,
+            )?;
+            self.connections
+                .insert(token, Connection::new(stream, self.config));
+        }
+    }
+
+    fn ready(&mut self, token: Token) {
+        let Some(connection) = self.connections.get_mut(&token) else {
+            return;
+        };
+
+        if !connection.ready(self.config, &self.shutdown) {
+            self.close(token);
+        }
+    }
+
+    fn check_timeouts(&mut self) {
+        let expired: Vec<Token> = self
+            .connections
+            .iter_mut()
+            .filter_map(|(token, connection)| {
+                (!connection.check_timeouts(self.config)).then_some(*token)
+            })
+            .collect();
+
+        for token in expired {
+            self.close(token);
+        }
+    }
+
+    fn close(&mut self, token: Token) {
+        if let Some(mut connection) = self.connections.remove(&token) {
+            let _ = self.poll.registry().deregister(&mut connection.stream);
+        }
+    }
+}
+
+/// One client connection and the state kept between readiness events
+struct Connection {
+    stream: TcpStream,
+    /// Parser for the request being received, which resumes where the last read stopped
+    parser: RequestParser,
+    read_buffer: Vec<u8>,
+    /// Serialized responses not yet accepted by the socket
+    write_buffer: Vec<u8>,
+    written: usize,
+    /// File body still to be read into the write buffer, a chunk at a time
+    file: Option<Take<File>>,
+    /// When the first byte of the request being received arrived
+    request_start: Option<Instant>,
+    /// When the socket last accepted response bytes
+    last_written: Instant,
+    served: usize,
+    /// The client has stopped sending
+    read_closed: bool,
+    /// Reading stopped with bytes possibly left in the socket
+    read_paused: bool,
+    /// The last response asked for the connection to be closed
+    closing: bool,
+    last_active: Instant,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, config: &ServerConfig) -> Connection {
+        Connection {
+            stream,
+            parser: RequestParser::with_config(config.parser.clone()),
+            read_buffer: Vec::new(),
+            write_buffer: Vec::new(),
+            written: 0,
+            file: None,
+            request_start: None,
+            last_written: Instant::now(),
+            served: 0,
+            read_closed: false,
+            read_paused: false,
+            closing: false,
+            last_active: Instant::now(),
+        }
+    }
+
+    /// Make as much progress as the socket allows, returning whether to keep it open
+    ///
+    /// Pipelined requests are answered one by one, and the next one is only parsed once
+    /// the previous response has been written, so a client that doesn't read can't make
+    /// responses pile up in memory. File bodies are read a chunk at a time as the socket
+    /// takes them, rather than into memory whole.
+    fn ready(&mut self, config: &ServerConfig, shutdown: &Shutdown) -> bool {
+        self.last_active = Instant::now();
+
+        if let Err(e) = self.fill(config) {
+            log::error!("Read request error: {}", e);
+            return false;
+        }
+
+        if !self.read_buffer.is_empty() {
+            self.request_start.get_or_insert_with(Instant::now);
+        }
+
+        loop {
+            match self.flush() {
+                Ok(true) => {}
+                Ok(false) => return true,
+                Err(e) => {
+                    log::error!("Send response error: {}", e);
+                    return false;
+                }
+            }
+
+            if self.closing {
+                return false;
+            }
+
+            let response = match self.parser.parse(&self.read_buffer) {
+                Ok(ParseStatus::Complete(request, consumed)) => {
+                    self.read_buffer.drain(..consumed);
+                    self.request_start = (!self.read_buffer.is_empty()).then(Instant::now);
+                    self.served += 1;
+
+                    Server::respond(config, &request, self.served, shutdown)
+                }
+                // Readiness is edge triggered, so what is left in the socket has to be
+                // read now rather than on a next event
+                Ok(ParseStatus::Incomplete) if self.read_paused => {
+                    if let Err(e) = self.fill(config) {
+                        log::error!("Read request error: {}", e);
+                        return false;
+                    }
+
+                    continue;
+                }
+                Ok(ParseStatus::Incomplete) => return !self.read_closed,
+                Err(e) => Server::reject(config, &e),
+            };
+
+            self.closing = !response.keep_alive();
+            self.last_written = Instant::now();
+
+            match response.send_head(&mut self.write_buffer) {
+                Ok(file) => self.file = file,
+                Err(e) => log::error!("Send response error: {}", e),
+            }
+        }
+    }
+
+    /// Waiting for a next request that hasn't started to arrive
+    fn is_idle(&self) -> bool {
+        !self.is_sending() && self.request_start.is_none()
+    }
+
+    /// Some of a response has yet to be written
+    fn is_sending(&self) -> bool {
+        !self.write_buffer.is_empty() || self.file.is_some()
+    }
+
+    /// Check the connection's timeouts, returning whether to keep it open
+    ///
+    /// A client gets `request_timeout` to send a request once it has started, and a
+    /// 408 if it doesn't make it. A response that the socket doesn't take for
+    /// `write_timeout` drops the connection.
+    fn check_timeouts(&mut self, config: &ServerConfig) -> bool {
+        if self.written < self.write_buffer.len() {
+            return self.last_written.elapsed() < config.write_timeout;
+        }
+
+        match self.request_start {
+            Some(start) if start.elapsed() >= config.request_timeout => {
+                self.closing = true;
+
+                // Best effort: the socket has taken everything so far, so a short
+                // response most likely fits
+                if Server::time_out(config)
+                    .send(&mut self.write_buffer)
+                    .is_ok()
+                {
+                    let _ = self.flush();
+                }
+
+                false
+            }
+            Some(_) => true,
+            None => self.last_active.elapsed() < config.keep_alive_timeout,
+        }
+    }
+
+    /// Read what the socket has buffered
+    ///
+    /// While responses are waiting to be written, reading pauses once a full head's
+    /// worth of pipelined requests is buffered, and resumes when those run out. It also
+    /// pauses past the most a request can take up, which the parser then rejects.
+    fn fill(&mut self, config: &ServerConfig) -> io::Result<()> {
+        let mut chunk = [0u8; 4096];
+        let limit = match self.is_sending() {
+            true => config.parser.max_head,
+            false => config.parser.max_request_len(),
+        };
+
+        self.read_paused = false;
+
+        while !self.read_closed {
+            if self.read_buffer.len() >= limit {
+                self.read_paused = true;
+                break;
+            }
+
+            match self.stream.read(&mut chunk) {
+                Ok(0) => self.read_closed = true,
+                Ok(len) => self.read_buffer.extend_from_slice(&chunk[..len]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write out pending responses, returning whether nothing is left
+    fn flush(&mut self) -> io::Result<bool> {
+        loop {
+            while self.written < self.write_buffer.len() {
+                match self.stream.write(&self.write_buffer[self.written..]) {
+                    Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                    Ok(len) => {
+                        self.written += len;
+                        self.last_written = Instant::now();
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+
+            self.write_buffer.clear();
+            self.written = 0;
+
+            if self.file.is_none() {
+                return Ok(true);
+            }
+
+            self.read_file()?;
+        }
+    }
+
+    /// Read the next chunk of the file body into the emptied write buffer
+    fn read_file(&mut self) -> io::Result<()> {
+        let file = self.file.as_mut().expect("a file is being sent");
+        let remaining = file.limit();
+        let len = file
+            .by_ref()
+            .take(FILE_CHUNK_SIZE)
+            .read_to_end(&mut self.write_buffer)?;
+
+        if len == 0 {
+            self.file = None;
+
+            // A file that shrank since would leave the body short of its `Content-Length`
+            if remaining != 0 && remaining != u64::MAX {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod event_loop_test {
+    use super::*;
+    use crate::testing::{self, Client, TestServer};
+    use std::fs;
+
+    fn start(config: ServerConfig) -> TestServer {
+        TestServer::start(config, |listener, config, shutdown| {
+            run(listener, config, shutdown)
+        })
+    }
+
+    #[test]
+    fn test_pipelined() {
+        let config = testing::config();
+        let health = fs::read(config.public_dir.join("health.html")).unwrap();
+        let index = fs::read(config.public_dir.join("index.html")).unwrap();
+        let server = start(config);
+        let mut client = server.connect();
+
+        client.send(b"GET /health HTTP/1.1\r\nHost: a\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\n");
+
+        assert_eq!(client.response().body, health);
+        assert_eq!(client.response().body, index);
+
+        client.send(b"GET /health HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n");
+
+        let response = client.response();
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("Connection"), Some("close"));
+        assert!(client.is_closed(Duration::from_secs(1)));
+
+        server.stop();
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        let server = start(testing::config());
+        let mut client = server.connect();
+
+        client.send(b"GET /health HTTP/1.1\r\nHo");
+
+        let response = client.response();
+
+        assert_eq!(response.status, 408);
+        assert_eq!(response.header("Connection"), Some("close"));
+        assert!(client.is_closed(Duration::from_secs(1)));
+
+        server.stop();
+    }
+
+    #[test]
+    fn test_drain() {
+        let server = start(testing::config());
+        let mut idle = server.connect();
+        let mut busy = server.connect();
+
+        idle.get("/health");
+        idle.response();
+        busy.send(b"GET /health HTTP/1.1\r\n");
+
+        let start = Instant::now();
+
+        server.shutdown.shutdown();
+
+        assert!(idle.is_closed(Duration::from_secs(1)));
+
+        // The request in flight is still answered, and closes its connection
+        busy.send(b"Host: a\r\n\r\n");
+
+        let response = busy.response();
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("Connection"), Some("close"));
+
+        server.join();
+
+        assert!(start.elapsed() < testing::config().grace_period);
+    }
+
+    /// A client reading slowly holds up its responses and reading its requests, but no
+    /// more than a file chunk and a head of them are buffered
+    #[test]
+    fn test_slow_reader() {
+        let big = (0..2 * 1024 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+        let public_dir = testing::public_dir("slow-reader", &[("big.bin", &big)]);
+        let mut config = ServerConfig {
+            public_dir: public_dir.clone(),
+            ..testing::config()
+        };
+
+        config.parser.max_head = 1024;
+
+        let shutdown = Shutdown::new();
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = Client::connect(listener.local_addr().unwrap());
+        let stream = listener.accept().unwrap().0;
+
+        stream.set_nonblocking(true).unwrap();
+
+        let mut connection = Connection::new(TcpStream::from_std(stream), &config);
+        let mut requests = b"GET /big.bin HTTP/1.1\r\nHost: a\r\n\r\n".repeat(2);
+
+        requests.extend(b"GET /health HTTP/1.1\r\nHost: a\r\n\r\n".repeat(50));
+        client.send(&requests);
+
+        let reader = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+
+            (0..52).map(|_| client.response()).collect::<Vec<_>>()
+        });
+        let (mut paused, mut stalled) = (false, false);
+
+        while !reader.is_finished() {
+            assert!(connection.ready(&config, &shutdown));
+            assert!(connection.write_buffer.len() <= FILE_CHUNK_SIZE as usize);
+            assert!(connection.read_buffer.len() <= config.parser.max_head + 4096);
+
+            paused |= connection.read_paused;
+            stalled |= connection.file.is_some();
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let responses = reader.join().unwrap();
+        let health = fs::read(public_dir.join("health.html")).unwrap();
+
+        assert!(paused && stalled);
+        assert_eq!(responses[0].body, big);
+        assert_eq!(responses[1].body, big);
+        assert!(responses[2..]
+            .iter()
+            .all(|response| response.body == health));
+        assert!(connection.is_idle());
+
+        fs::remove_dir_all(public_dir).unwrap();
+    }
+}
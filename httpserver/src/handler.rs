@@ -1,27 +1,42 @@
 use http::{
-    request::{HttpRequest, Rescourse, Version},
+    request::HttpRequest,
     response::{HttpResponse, HttpStatus},
+    uri::Query,
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, env, fs};
+use std::fs;
+use std::path::PathBuf;
 
-pub trait Handler {
-    fn handle(request: &HttpRequest) -> HttpResponse;
+use crate::log;
+use crate::server::ServerConfig;
 
-    fn load_file(file_name: &str) -> Option<String> {
-        let default_path = format!("{}/public", env!("CARGO_MANIFEST_DIR"));
-        let public_path = env::var("PUBLIC_PATH").unwrap_or(default_path);
-        let full_path = format!("{}/{}", public_path, file_name);
+pub trait Handler {
+    fn handle(config: &ServerConfig, request: &HttpRequest) -> HttpResponse;
 
-        let file_content = fs::read_to_string(full_path);
+    fn load_file(config: &ServerConfig, file_name: &str) -> Option<Vec<u8>> {
+        let file_content = fs::read(Self::public_file(config, file_name));
 
         file_content.ok()
     }
+
+    /// Location of a file under the public directory
+    fn public_file(config: &ServerConfig, file_name: &str) -> PathBuf {
+        config.public_dir.join(file_name)
+    }
 }
 
+/// Methods the router dispatches to a handler, advertised in `Allow`
+pub const ALLOWED_METHODS: &str = "GET, HEAD, OPTIONS";
+
+/// Number of orders per page of `/api/shipping/orders`
+const ORDERS_PER_PAGE: usize = 10;
+
 pub struct StaticPageHandler;
 pub struct PageNotFoundHandler;
 pub struct WebServiceHandler;
+pub struct OptionsHandler;
+pub struct MethodNotAllowedHandler;
+pub struct NotImplementedHandler;
 
 #[derive(Serialize, Deserialize)]
 pub struct OrderStatus {
@@ -31,94 +46,133 @@ pub struct OrderStatus {
 }
 
 impl Handler for PageNotFoundHandler {
-    fn handle(request: &HttpRequest) -> HttpResponse {
-        HttpResponse::new(
-            Version::V1_1,
-            HttpStatus::NOT_FOUND,
-            None,
-            Self::load_file("404.html"),
-        )
+    fn handle(config: &ServerConfig, _request: &HttpRequest) -> HttpResponse {
+        HttpResponse::builder()
+            .status(HttpStatus::NOT_FOUND)
+            .html(Self::load_file(config, "404.html"))
+            .build()
     }
 }
 
-impl Handler for StaticPageHandler {
-    fn handle(request: &HttpRequest) -> HttpResponse {
-        let Rescourse::Path(s) = &request.resource;
-        let paths: Vec<&str> = s.split("/").collect();
-
-        match paths[1] {
-            "" => HttpResponse::new(
-                Version::V1_1,
-                HttpStatus::OK,
-                None,
-                Self::load_file("index.html"),
-            ),
-            "health" => HttpResponse::new(
-                Version::V1_1,
-                HttpStatus::OK,
-                None,
-                Self::load_file("health.html"),
-            ),
-            path => match Self::load_file(path) {
-                Some(contents) => {
-                    let mut headers: HashMap<String, String> = HashMap::new();
-
-                    headers.insert("Content-Type".into(), "text/html".into());
-
-                    if path.ends_with(".css") {
-                        headers.insert("Content-Type".into(), "text/css".into());
-                    } else if path.ends_with(".js") {
-                        headers.insert("Content-Type".into(), "text/javascript".into());
-                    }
+impl Handler for OptionsHandler {
+    fn handle(_config: &ServerConfig, _request: &HttpRequest) -> HttpResponse {
+        HttpResponse::builder()
+            .status(HttpStatus::NO_CONTENT)
+            .header("Allow", ALLOWED_METHODS)
+            .build()
+    }
+}
 
-                    HttpResponse::new(Version::V1_1, HttpStatus::OK, Some(headers), Some(contents))
-                }
-                None => HttpResponse::new(
-                    Version::V1_1,
-                    HttpStatus::NOT_FOUND,
-                    None,
-                    Self::load_file("404.html"),
-                ),
+impl Handler for MethodNotAllowedHandler {
+    fn handle(_config: &ServerConfig, _request: &HttpRequest) -> HttpResponse {
+        HttpResponse::builder()
+            .status(HttpStatus::METHOD_NOT_ALLOWED)
+            .header("Allow", ALLOWED_METHODS)
+            .build()
+    }
+}
+
+impl Handler for NotImplementedHandler {
+    fn handle(_config: &ServerConfig, _request: &HttpRequest) -> HttpResponse {
+        HttpResponse::builder()
+            .status(HttpStatus::NOT_IMPLEMENTED)
+            .build()
+    }
+}
+
+impl Handler for StaticPageHandler {
+    fn handle(config: &ServerConfig, request: &HttpRequest) -> HttpResponse {
+        // The path is already normalized, so it can't point outside the public directory.
+        match request.resource.path() {
+            "/" => HttpResponse::builder()
+                .html(Self::load_file(config, "index.html"))
+                .build(),
+            "/health" => HttpResponse::builder()
+                .html(Self::load_file(config, "health.html"))
+                .build(),
+            path => match HttpResponse::builder().file(Self::public_file(config, &path[1..])) {
+                Ok(builder) => builder.build(),
+                Err(_) => PageNotFoundHandler::handle(config, request),
             },
         }
     }
 }
 
 impl WebServiceHandler {
-    fn load_json() -> Vec<OrderStatus> {
-        let default_path = format!("{}/data", env!("CARGO_MANIFEST_DIR"));
-        let public_path = env::var("PUBLIC_PATH").unwrap_or(default_path);
-        let full_path = format!("{}/{}", public_path, "orders.json");
-
-        let json_content = fs::read_to_string(full_path);
-
-        let orders: Vec<OrderStatus> =
-            serde_json::from_str(json_content.unwrap().as_str()).unwrap();
+    /// Orders from `orders.json` in the data directory, or `None` if it can't be read
+    fn load_json(config: &ServerConfig) -> Option<Vec<OrderStatus>> {
+        let full_path = config.data_dir.join("orders.json");
+        let orders = fs::read_to_string(&full_path)
+            .map_err(|e| e.to_string())
+            .and_then(|json_content| {
+                serde_json::from_str(&json_content).map_err(|e| e.to_string())
+            });
+
+        match orders {
+            Ok(orders) => Some(orders),
+            Err(e) => {
+                log::error!("Load {} error: {}", full_path.display(), e);
+                None
+            }
+        }
+    }
 
-        orders
+    /// Apply the `status` filter and `page` selection of `/api/shipping/orders`,
+    /// or `None` if either parameter is malformed
+    fn query_orders(orders: Vec<OrderStatus>, query: &Query) -> Option<Vec<OrderStatus>> {
+        let status = match query.get("status") {
+            Some(status) => Some(status.parse::<bool>().ok()?),
+            None => None,
+        };
+        let page = match query.get("page") {
+            Some(page) => page.parse::<usize>().ok().filter(|page| *page > 0)?,
+            None => 1,
+        };
+
+        Some(
+            orders
+                .into_iter()
+                .filter(|order| status.is_none_or(|status| order.order_status == status))
+                .skip((page - 1) * ORDERS_PER_PAGE)
+                .take(ORDERS_PER_PAGE)
+                .collect(),
+        )
     }
 }
 
 impl Handler for WebServiceHandler {
-    fn handle(request: &HttpRequest) -> HttpResponse {
-        let Rescourse::Path(s) = &request.resource;
-        let paths: Vec<&str> = s.split("/").collect();
-
-        match paths[2] {
-            "shipping" if paths.len() > 2 && paths[3] == "orders" => {
-                let mut headers: HashMap<String, String> = HashMap::new();
-                let body = Some(serde_json::to_string(&Self::load_json()).unwrap());
-
-                headers.insert("Content-Type".into(), "application/json".into());
+    fn handle(config: &ServerConfig, request: &HttpRequest) -> HttpResponse {
+        let paths: Vec<&str> = request.resource.path().split('/').collect();
+
+        match paths[..] {
+            [_, _, "shipping", "orders"] => {
+                let default_query = Query::default();
+                let query = request.resource.query().unwrap_or(&default_query);
+                let orders = match Self::load_json(config) {
+                    Some(orders) => orders,
+                    None => {
+                        return HttpResponse::builder()
+                            .status(HttpStatus::INTERNAL_SERVER_ERROR)
+                            .build()
+                    }
+                };
+                let orders = match Self::query_orders(orders, query) {
+                    Some(orders) => orders,
+                    None => {
+                        return HttpResponse::builder()
+                            .status(HttpStatus::BAD_REQUEST)
+                            .build()
+                    }
+                };
 
-                HttpResponse::new(Version::V1_1, HttpStatus::OK, Some(headers), body)
+                match HttpResponse::builder().json(&orders) {
+                    Ok(builder) => builder.build(),
+                    Err(_) => HttpResponse::builder()
+                        .status(HttpStatus::INTERNAL_SERVER_ERROR)
+                        .build(),
+                }
             }
-            _ => HttpResponse::new(
-                Version::V1_1,
-                HttpStatus::NOT_FOUND,
-                None,
-                Self::load_file("404.html"),
-            ),
+            _ => PageNotFoundHandler::handle(config, request),
         }
     }
 }
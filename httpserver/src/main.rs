@@ -1,11 +1,74 @@
+#[cfg(feature = "async")]
+mod async_server;
+mod config;
+mod event_loop;
 mod handler;
+mod log;
+mod pool;
+mod reader;
 mod router;
 mod server;
+mod shutdown;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "io-uring")]
+mod uring;
 
+use clap::Parser;
+use config::Cli;
 use server::Server;
+use shutdown::Shutdown;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use std::{env, process, thread};
 
 fn main() {
-    let server = Server::new("localhost:3000");
+    let cli = Cli::parse();
+    let check = cli.check;
+    let config = match config::load(cli, |var| env::var(var).ok()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            process::exit(2);
+        }
+    };
 
-    server.run();
+    if check {
+        if let Err(e) = config::check_addresses(&config) {
+            eprintln!("Invalid configuration: {}", e);
+            process::exit(2);
+        }
+
+        println!("Configuration OK: {:#?}", config);
+        return;
+    }
+
+    log::set_level(config.log_level);
+
+    let server = Server::new(config);
+
+    shutdown_on_signals(server.shutdown_handle());
+
+    if let Err(e) = server.run() {
+        log::error!("Server error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Shut down gracefully on the first SIGINT or SIGTERM, and exit right away on a second
+fn shutdown_on_signals(shutdown: Shutdown) {
+    let mut signals = Signals::new([SIGINT, SIGTERM]).unwrap();
+
+    thread::spawn(move || {
+        let mut signals = signals.forever();
+
+        if signals.next().is_some() {
+            log::info!("Shutting down, a second signal exits right away");
+            shutdown.shutdown();
+        }
+
+        if signals.next().is_some() {
+            process::exit(1);
+        }
+    });
 }
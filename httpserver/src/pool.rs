@@ -0,0 +1,184 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::log;
+
+/// How often `join_until` checks whether the workers are done
+const JOIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A fixed number of worker threads running one handler over queued jobs
+///
+/// The queue is bounded, so a busy pool refuses work instead of piling it up. A job that
+/// panics is logged and its worker goes on with the next one.
+pub struct ThreadPool<T: Send + 'static> {
+    workers: Vec<JoinHandle<()>>,
+    sender: Option<SyncSender<T>>,
+}
+
+impl<T: Send + 'static> ThreadPool<T> {
+    /// Start `size` workers that share a queue of up to `queue_size` waiting jobs
+    ///
+    /// # Panics
+    ///
+    /// If `size` is zero.
+    pub fn new<F>(size: usize, queue_size: usize, handler: F) -> ThreadPool<T>
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        assert!(size > 0, "a thread pool needs at least one worker");
+
+        let (sender, receiver) = mpsc::sync_channel(queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+        let workers = (0..size)
+            .map(|id| {
+                let receiver = Arc::clone(&receiver);
+                let handler = Arc::clone(&handler);
+
+                thread::Builder::new()
+                    .name(format!("worker-{}", id))
+                    .spawn(move || Self::work(id, &receiver, &*handler))
+                    .unwrap()
+            })
+            .collect();
+
+        ThreadPool {
+            workers,
+            sender: Some(sender),
+        }
+    }
+
+    /// Queue a job, handing it back if the queue is full
+    pub fn execute(&self, job: T) -> Result<(), T> {
+        let sender = self.sender.as_ref().expect("the sender lives until drop");
+
+        sender.try_send(job).map_err(|e| match e {
+            TrySendError::Full(job) | TrySendError::Disconnected(job) => job,
+        })
+    }
+
+    /// Stop taking jobs and wait for the queued ones until `deadline`
+    ///
+    /// Returns how many workers were still busy by then; they are left running, to be
+    /// cut short when the process exits.
+    pub fn join_until(mut self, deadline: Instant) -> usize {
+        drop(self.sender.take());
+
+        let workers = std::mem::take(&mut self.workers);
+
+        while workers.iter().any(|w| !w.is_finished()) && Instant::now() < deadline {
+            thread::sleep(JOIN_POLL_INTERVAL);
+        }
+
+        let mut busy = 0;
+
+        for worker in workers {
+            if worker.is_finished() {
+                let _ = worker.join();
+            } else {
+                busy += 1;
+            }
+        }
+
+        busy
+    }
+
+    fn work(id: usize, receiver: &Mutex<Receiver<T>>, handler: &(impl Fn(T) + ?Sized)) {
+        loop {
+            // The guard is dropped before the job runs, so a panic can't poison the lock.
+            let job = match receiver.lock().unwrap().recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+
+            if panic::catch_unwind(AssertUnwindSafe(|| handler(job))).is_err() {
+                log::error!("Worker {} recovered from a panicking job", id);
+            }
+        }
+    }
+}
+
+/// Waits for queued jobs to finish before the workers are gone
+impl<T: Send + 'static> Drop for ThreadPool<T> {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod pool_test {
+    use super::*;
+    use std::sync::mpsc::Sender;
+
+    /// A pool whose jobs report that they started, then wait until they are released
+    fn blocking_pool(
+        size: usize,
+        queue_size: usize,
+    ) -> (ThreadPool<u32>, Receiver<u32>, Sender<()>) {
+        let (started, started_receiver) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        let pool = ThreadPool::new(size, queue_size, move |job| {
+            started.send(job).unwrap();
+            released.lock().unwrap().recv().unwrap();
+        });
+
+        (pool, started_receiver, release)
+    }
+
+    #[test]
+    fn test_execute_full_queue() {
+        let (pool, started, release) = blocking_pool(1, 1);
+
+        assert_eq!(pool.execute(1), Ok(()));
+        assert_eq!(started.recv(), Ok(1));
+        assert_eq!(pool.execute(2), Ok(()));
+        assert_eq!(pool.execute(3), Err(3));
+
+        release.send(()).unwrap();
+        assert_eq!(started.recv(), Ok(2));
+        release.send(()).unwrap();
+    }
+
+    #[test]
+    fn test_panicking_job() {
+        let (done, finished) = mpsc::channel();
+        let done = Mutex::new(done);
+        let pool = ThreadPool::new(1, 4, move |job: u32| {
+            if job == 0 {
+                panic!("job 0 fails");
+            }
+
+            done.lock().unwrap().send(job).unwrap();
+        });
+
+        assert_eq!(pool.execute(0), Ok(()));
+        assert_eq!(pool.execute(1), Ok(()));
+        assert_eq!(finished.recv(), Ok(1));
+    }
+
+    #[test]
+    fn test_join_until() {
+        let (pool, started, release) = blocking_pool(2, 1);
+
+        assert_eq!(pool.execute(1), Ok(()));
+        assert_eq!(started.recv(), Ok(1));
+        assert_eq!(
+            pool.join_until(Instant::now() + Duration::from_millis(100)),
+            1
+        );
+
+        release.send(()).unwrap();
+
+        let (pool, _, _) = blocking_pool(2, 1);
+
+        assert_eq!(pool.join_until(Instant::now() + Duration::from_secs(5)), 0);
+    }
+}
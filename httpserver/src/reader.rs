@@ -0,0 +1,287 @@
+use std::io::{self, Read};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use http::request::{HttpRequest, ParseError, ParseStatus, RequestParser};
+
+use crate::log;
+use crate::server::ServerConfig;
+use crate::shutdown::Shutdown;
+
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// How often a connection waiting for its next request checks for shutdown
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Why no request could be read from a connection that is still open
+#[derive(Debug)]
+pub enum ReadError {
+    Parse(ParseError),
+    /// The client started a request but didn't finish it within the request timeout
+    Timeout,
+}
+
+/// Reads requests off a blocking connection
+///
+/// Bytes are appended to one buffer until the parser has a full request, head and body.
+/// The parser is run after every read and rejects a request as soon as it outgrows its
+/// limits, so the buffer stays within [`ParserConfig::max_request_len`] plus a read.
+/// Bytes read past the end of a request stay in the buffer for the next one.
+///
+/// [`ParserConfig::max_request_len`]: http::request::ParserConfig::max_request_len
+pub struct ConnectionReader {
+    buffer: Vec<u8>,
+    parser: RequestParser,
+}
+
+impl ConnectionReader {
+    pub fn new(config: &ServerConfig) -> ConnectionReader {
+        ConnectionReader {
+            buffer: Vec::new(),
+            parser: RequestParser::with_config(config.parser.clone()),
+        }
+    }
+
+    /// Read the next request, or `None` once the connection should be closed quietly
+    ///
+    /// The connection may sit idle for `keep_alive_timeout` between requests, or until
+    /// shutdown is requested. Once the first byte of a request is in, the rest has to
+    /// follow within `request_timeout`, however it is spread over reads, which is what
+    /// stops slowloris clients.
+    pub fn read_request(
+        &mut self,
+        stream: &mut TcpStream,
+        config: &ServerConfig,
+        shutdown: &Shutdown,
+    ) -> Result<Option<HttpRequest>, ReadError> {
+        let mut chunk = [0u8; READ_CHUNK_SIZE];
+        let idle_until = Instant::now() + config.keep_alive_timeout;
+        // Leftovers of a pipelined request count as its start
+        let mut deadline =
+            (!self.buffer.is_empty()).then(|| Instant::now() + config.request_timeout);
+
+        loop {
+            if let ParseStatus::Complete(request, consumed) =
+                self.parser.parse(&self.buffer).map_err(ReadError::Parse)?
+            {
+                self.buffer.drain(..consumed);
+
+                return Ok(Some(request));
+            }
+
+            let timeout = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => idle_until
+                    .saturating_duration_since(Instant::now())
+                    .min(SHUTDOWN_POLL_INTERVAL),
+            };
+
+            if timeout.is_zero() {
+                return match deadline {
+                    Some(_) => Err(ReadError::Timeout),
+                    None => Ok(None),
+                };
+            }
+
+            if let Err(e) = stream.set_read_timeout(Some(timeout)) {
+                log::error!("Set timeout error: {}", e);
+                return Ok(None);
+            }
+
+            match stream.read(&mut chunk) {
+                Ok(0) => return Ok(None),
+                Ok(len) => {
+                    self.buffer.extend_from_slice(&chunk[..len]);
+                    deadline.get_or_insert_with(|| Instant::now() + config.request_timeout);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    match deadline {
+                        Some(_) => return Err(ReadError::Timeout),
+                        // The idle timeout ran out, or shutdown was requested
+                        None if shutdown.is_requested() || Instant::now() >= idle_until => {
+                            return Ok(None);
+                        }
+                        None => {}
+                    }
+                }
+                Err(e) => {
+                    log::error!("Read request error: {}", e);
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod reader_test {
+    use super::*;
+    use crate::testing;
+    use http::request::ParserConfig;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Both ends of a loopback connection, the client's first
+    fn socket_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        (client, listener.accept().unwrap().0)
+    }
+
+    #[test]
+    fn test_read_pipelined() {
+        let config = testing::config();
+        let shutdown = Shutdown::new();
+        let (mut client, mut stream) = socket_pair();
+        let mut reader = ConnectionReader::new(&config);
+
+        client
+            .write_all(b"GET /a HTTP/1.1\r\nHost: a\r\n\r\nGET /b HTTP/1.1\r\nHost: a\r\n\r\n")
+            .unwrap();
+
+        let request = reader
+            .read_request(&mut stream, &config, &shutdown)
+            .unwrap();
+
+        assert_eq!(request.unwrap().resource.path(), "/a");
+        assert_eq!(reader.buffer, b"GET /b HTTP/1.1\r\nHost: a\r\n\r\n");
+
+        // The leftovers make a whole request without another read
+        let request = reader
+            .read_request(&mut stream, &config, &shutdown)
+            .unwrap();
+
+        assert_eq!(request.unwrap().resource.path(), "/b");
+        assert!(reader.buffer.is_empty());
+
+        drop(client);
+
+        assert!(matches!(
+            reader.read_request(&mut stream, &config, &shutdown),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        let config = testing::config();
+        let shutdown = Shutdown::new();
+        let (mut client, mut stream) = socket_pair();
+        let mut reader = ConnectionReader::new(&config);
+
+        client.write_all(b"GET /a HTTP/1.1\r\nHo").unwrap();
+
+        let start = Instant::now();
+
+        assert!(matches!(
+            reader.read_request(&mut stream, &config, &shutdown),
+            Err(ReadError::Timeout)
+        ));
+        assert!(start.elapsed() >= config.request_timeout);
+
+        // Leftovers of a pipelined request start its clock right away
+        let (mut client, mut stream) = socket_pair();
+        let mut reader = ConnectionReader::new(&config);
+
+        client
+            .write_all(b"GET /a HTTP/1.1\r\nHost: a\r\n\r\nGET /b HTTP/1.1\r\n")
+            .unwrap();
+
+        assert!(reader
+            .read_request(&mut stream, &config, &shutdown)
+            .unwrap()
+            .is_some());
+        assert!(matches!(
+            reader.read_request(&mut stream, &config, &shutdown),
+            Err(ReadError::Timeout)
+        ));
+
+        // An idle connection is closed quietly instead
+        let config = ServerConfig {
+            keep_alive_timeout: Duration::from_millis(200),
+            ..testing::config()
+        };
+        let (_client, mut stream) = socket_pair();
+        let mut reader = ConnectionReader::new(&config);
+
+        assert!(matches!(
+            reader.read_request(&mut stream, &config, &shutdown),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn test_read_large_request() {
+        let config = testing::config();
+        let shutdown = Shutdown::new();
+        let (mut client, mut stream) = socket_pair();
+        let mut reader = ConnectionReader::new(&config);
+        let body = vec![b'x'; 16 * READ_CHUNK_SIZE];
+        let mut request = format!(
+            "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+
+        request.extend(&body);
+
+        let writer = thread::spawn(move || client.write_all(&request).unwrap());
+        let request = reader
+            .read_request(&mut stream, &config, &shutdown)
+            .unwrap();
+
+        writer.join().unwrap();
+
+        assert_eq!(request.unwrap().body, Some(body));
+        assert!(reader.buffer.is_empty());
+    }
+
+    #[test]
+    fn test_read_limit() {
+        let config = ServerConfig {
+            parser: ParserConfig {
+                max_head: 1024,
+                max_body: 1024,
+                ..ParserConfig::strict()
+            },
+            ..testing::config()
+        };
+        let shutdown = Shutdown::new();
+        let (mut client, mut stream) = socket_pair();
+        let mut reader = ConnectionReader::new(&config);
+
+        // A chunked body is only found too large once enough of it is in, well before all
+        // of the 64 KiB sent here
+        let writer = thread::spawn(move || {
+            let _ = client
+                .write_all(b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n");
+
+            for _ in 0..256 {
+                if client.write_all(b"100\r\n").is_err() || client.write_all(&[b'x'; 256]).is_err()
+                {
+                    break;
+                }
+
+                let _ = client.write_all(b"\r\n");
+            }
+        });
+
+        assert!(matches!(
+            reader.read_request(&mut stream, &config, &shutdown),
+            Err(ReadError::Parse(ParseError::BodyTooLarge))
+        ));
+        assert!(config.parser.max_request_len() + READ_CHUNK_SIZE < 64 * 1024);
+        assert!(reader.buffer.len() <= config.parser.max_request_len() + READ_CHUNK_SIZE);
+
+        drop(stream);
+        writer.join().unwrap();
+    }
+}
@@ -1,38 +1,32 @@
-use crate::handler::{Handler, PageNotFoundHandler, StaticPageHandler, WebServiceHandler};
+use crate::handler::{
+    Handler, MethodNotAllowedHandler, NotImplementedHandler, OptionsHandler, StaticPageHandler,
+    WebServiceHandler,
+};
+use crate::log;
+use crate::server::ServerConfig;
 use http::{
-    request::{HttpRequest, Method, Rescourse},
+    request::{HttpRequest, Method},
     response::HttpResponse,
 };
-use std::io::Write;
 
 pub struct Router;
 
 impl Router {
-    pub fn route(request: HttpRequest, stream: &mut impl Write) {
+    pub fn route(config: &ServerConfig, request: &HttpRequest) -> HttpResponse {
         match request.mothod {
-            Method::Get => match &request.resource {
-                Rescourse::Path(s) => {
-                    let paths: Vec<&str> = s.split("/").collect();
-
-                    println!("{:?}", paths);
+            Method::Get | Method::Head => {
+                let paths: Vec<&str> = request.resource.path().split('/').collect();
 
-                    match paths[1] {
-                        "api" => {
-                            let response: HttpResponse = WebServiceHandler::handle(&request);
-                            response.send(stream).unwrap();
-                        }
-                        _ => {
-                            let response: HttpResponse = StaticPageHandler::handle(&request);
+                log::debug!("{:?}", paths);
 
-                            response.send(stream).unwrap();
-                        }
-                    }
+                match paths[1] {
+                    "api" => WebServiceHandler::handle(config, request),
+                    _ => StaticPageHandler::handle(config, request),
                 }
-            },
-            _ => {
-                let response: HttpResponse = PageNotFoundHandler::handle(&request);
-                response.send(stream).unwrap();
             }
+            Method::Options => OptionsHandler::handle(config, request),
+            Method::Extension(_) => NotImplementedHandler::handle(config, request),
+            _ => MethodNotAllowedHandler::handle(config, request),
         }
     }
 }
@@ -1,35 +1,605 @@
-use std::io::Read;
-use std::net::TcpListener;
+use std::io;
+use std::net::{self, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-use http::request::HttpRequest;
+use http::{
+    request::{HttpRequest, ParseError, ParserConfig},
+    response::{HttpResponse, HttpStatus},
+};
 
+#[cfg(feature = "async")]
+use crate::async_server;
+use crate::event_loop;
+use crate::log::{self, LogLevel};
+use crate::pool::ThreadPool;
+use crate::reader::{ConnectionReader, ReadError};
 use crate::router::Router;
+use crate::shutdown::Shutdown;
+#[cfg(feature = "io-uring")]
+use crate::uring;
 
-pub struct Server<'a> {
-    socket_addr: &'a str,
+/// Seconds a client is asked to wait when every worker is busy
+const RETRY_AFTER_SECS: u64 = 1;
+
+const LISTENER: mio::Token = mio::Token(0);
+const WAKER: mio::Token = mio::Token(1);
+
+/// How connections are served
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// Blocking sockets, a pool thread per connection
+    Threaded,
+    /// Non-blocking sockets multiplexed over a few epoll event loops
+    EventLoop,
+    /// Batched accepts, reads and writes submitted to a few io_uring rings
+    #[cfg(feature = "io-uring")]
+    IoUring,
+    /// A task per connection on a Tokio runtime
+    #[cfg(feature = "async")]
+    Async,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Backend, String> {
+        match s {
+            "threaded" => Ok(Backend::Threaded),
+            "event-loop" => Ok(Backend::EventLoop),
+            #[cfg(feature = "io-uring")]
+            "io-uring" => Ok(Backend::IoUring),
+            #[cfg(not(feature = "io-uring"))]
+            "io-uring" => Err("built without the io-uring feature".into()),
+            #[cfg(feature = "async")]
+            "async" => Ok(Backend::Async),
+            #[cfg(not(feature = "async"))]
+            "async" => Err("built without the async feature".into()),
+            _ => Err(format!("unknown backend {:?}", s)),
+        }
+    }
+}
+
+/// Settings for a [`Server`]
+#[derive(Debug)]
+pub struct ServerConfig {
+    /// Addresses to listen on, each served by its own backend
+    pub bind: Vec<String>,
+    /// Directory static pages are served from
+    pub public_dir: PathBuf,
+    /// Directory of the data behind `/api`
+    pub data_dir: PathBuf,
+    /// Value of the `Server` header added to responses, or `None` to leave it out
+    pub server_header: Option<String>,
+    /// How long a kept-alive connection may wait for its next request
+    pub keep_alive_timeout: Duration,
+    /// How long a client may take to send a whole request once it has started, before
+    /// it gets a 408
+    pub request_timeout: Duration,
+    /// How long sending a response may stall before the connection is dropped
+    pub write_timeout: Duration,
+    /// Limits and strictness of request parsing, which also bound how far a connection's
+    /// read buffer grows
+    pub parser: ParserConfig,
+    /// Requests served on one connection before it is closed
+    pub max_requests: usize,
+    /// How long requests in flight may take to finish once shutdown is requested
+    pub grace_period: Duration,
+    pub backend: Backend,
+    /// Threads serving connections on each bound address: each pool thread serves one
+    /// connection at a time, each event loop, ring or runtime thread any number of them
+    pub workers: usize,
+    /// Accepted connections waiting for a pool thread before new ones get a 503
+    pub queue_size: usize,
+    pub log_level: LogLevel,
 }
 
-impl<'a> Server<'a> {
-    pub fn new(socket_addr: &'a str) -> Server<'a> {
-        Server { socket_addr }
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            bind: vec!["localhost:3000".into()],
+            public_dir: PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/public")),
+            data_dir: PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/data")),
+            server_header: Some(format!(
+                "{}/{}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            )),
+            keep_alive_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(10),
+            write_timeout: Duration::from_secs(10),
+            parser: ParserConfig::strict(),
+            max_requests: 100,
+            grace_period: Duration::from_secs(10),
+            backend: Backend::Threaded,
+            workers: 16,
+            queue_size: 64,
+            log_level: LogLevel::Info,
+        }
+    }
+}
+
+pub struct Server {
+    config: Arc<ServerConfig>,
+    shutdown: Shutdown,
+}
+
+impl Server {
+    pub fn new(config: ServerConfig) -> Server {
+        Server {
+            config: Arc::new(config),
+            shutdown: Shutdown::new(),
+        }
+    }
+
+    /// Handle that stops [`Server::run`], from any thread
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Serve connections until shutdown is requested and they are drained
+    ///
+    /// Fails without serving anything if one of the addresses can't be bound.
+    pub fn run(&self) -> io::Result<()> {
+        let listeners = self
+            .config
+            .bind
+            .iter()
+            .map(|addr| {
+                TcpListener::bind(addr.as_str())
+                    .map_err(|e| io::Error::new(e.kind(), format!("bind {}: {}", addr, e)))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        log::info!(
+            "Running on: {} ({:?})",
+            self.config.bind.join(", "),
+            self.config.backend
+        );
+
+        thread::scope(|scope| {
+            for listener in listeners {
+                scope.spawn(move || self.serve(listener));
+            }
+        });
+
+        log::info!("Stopped");
+
+        Ok(())
+    }
+
+    /// Serve one listener with the configured backend
+    fn serve(&self, connection_listener: TcpListener) {
+        let shutdown = &self.shutdown;
+
+        match self.config.backend {
+            Backend::Threaded => {
+                if let Err(e) = self.run_threaded(connection_listener) {
+                    log::error!("Accept loop error: {}", e);
+                }
+            }
+            Backend::EventLoop => {
+                if let Err(e) = event_loop::run(connection_listener, &self.config, shutdown) {
+                    log::error!("Event loop error: {}", e);
+                }
+            }
+            #[cfg(feature = "io-uring")]
+            Backend::IoUring => {
+                if let Err(e) = uring::run(connection_listener, &self.config, shutdown) {
+                    log::error!("io_uring error: {}", e);
+                }
+            }
+            #[cfg(feature = "async")]
+            Backend::Async => {
+                if let Err(e) = async_server::run(connection_listener, &self.config, shutdown) {
+                    log::error!("Async runtime error: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Accept connections into the pool until shutdown, then wait for the pool to drain
+    ///
+    /// The listener is polled rather than blocked on, so that a shutdown request can
+    /// wake the loop up.
+    fn run_threaded(&self, connection_listener: TcpListener) -> io::Result<()> {
+        let config = Arc::clone(&self.config);
+        let shutdown = self.shutdown.clone();
+        let pool = ThreadPool::new(self.config.workers, self.config.queue_size, move |stream| {
+            Self::handle_connection(&config, &shutdown, stream)
+        });
+        let mut poll = mio::Poll::new()?;
+        let mut events = mio::Events::with_capacity(16);
+        let waker = mio::Waker::new(poll.registry(), WAKER)?;
+        let mut listener = {
+            connection_listener.set_nonblocking(true)?;
+            mio::net::TcpListener::from_std(connection_listener)
+        };
+
+        poll.registry()
+            .register(&mut listener, LISTENER, mio::Interest::READABLE)?;
+        self.shutdown.on_shutdown(move || {
+            let _ = waker.wake();
+        });
+
+        while !self.shutdown.is_requested() {
+            match poll.poll(&mut events, None) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => result?,
+            }
+
+            loop {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => TcpStream::from(stream),
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        log::error!("Accept connection error: {}", e);
+                        break;
+                    }
+                };
+
+                if let Err(e) = stream.set_nonblocking(false) {
+                    log::error!("Accept connection error: {}", e);
+                    continue;
+                }
+
+                self.dispatch(&pool, stream);
+            }
+        }
+
+        // Stop accepting right away, connections still queued get served
+        drop(listener);
+
+        let deadline = self.shutdown.deadline(self.config.grace_period);
+        let busy = pool.join_until(deadline.expect("shutdown was requested"));
+
+        if busy > 0 {
+            log::error!("Dropping {} connections after the grace period", busy);
+        }
+
+        Ok(())
     }
 
-    pub fn run(&self) {
-        let connection_listener = TcpListener::bind(self.socket_addr).unwrap();
+    /// Hand a connection to the pool, or turn it away with a 503 when the pool is full
+    ///
+    /// The 503 is written on the accept thread, so a client that doesn't read only
+    /// holds accepting up for `write_timeout`.
+    fn dispatch(&self, pool: &ThreadPool<TcpStream>, stream: TcpStream) {
+        if let Err(mut stream) = pool.execute(stream) {
+            let mut response = HttpResponse::builder()
+                .status(HttpStatus::SERVICE_UNAVAILABLE)
+                .header("Retry-After", RETRY_AFTER_SECS.to_string())
+                .header("Connection", "close")
+                .build();
 
-        println!("Running on: {}", self.socket_addr);
+            Self::add_server_headers(&self.config, &mut response);
 
-        for stream in connection_listener.incoming() {
-            let mut stream = stream.unwrap();
-            let mut request_buffer = [0u8; 256];
+            if let Err(e) = stream.set_write_timeout(Some(self.config.write_timeout)) {
+                log::error!("Set timeout error: {}", e);
+                return;
+            }
 
-            stream.read(&mut request_buffer).unwrap();
+            if Self::send(response, &mut stream) {
+                return;
+            }
 
-            let request: HttpRequest = String::from_utf8(request_buffer.to_vec()).unwrap().into();
+            // Signal the end of the response before the socket is dropped
+            let _ = stream.shutdown(net::Shutdown::Write);
+        }
+    }
+
+    /// Serve requests from one connection, in order, until either side closes it
+    fn handle_connection(config: &ServerConfig, shutdown: &Shutdown, mut stream: TcpStream) {
+        let mut reader = ConnectionReader::new(config);
+
+        if let Err(e) = stream.set_write_timeout(Some(config.write_timeout)) {
+            log::error!("Set timeout error: {}", e);
+            return;
+        }
+
+        for served in 1.. {
+            let response = match reader.read_request(&mut stream, config, shutdown) {
+                Ok(Some(request)) => Self::respond(config, &request, served, shutdown),
+                Ok(None) => return,
+                Err(ReadError::Parse(e)) => Self::reject(config, &e),
+                Err(ReadError::Timeout) => Self::time_out(config),
+            };
+
+            if !Self::send(response, &mut stream) {
+                return;
+            }
+        }
+    }
 
-            // println!("{:#?}", request);
+    /// Route a request and fit the response to the request and the connection, which
+    /// has served `served` requests with this one and is closed after it once shutdown
+    /// is requested
+    pub(crate) fn respond(
+        config: &ServerConfig,
+        request: &HttpRequest,
+        served: usize,
+        shutdown: &Shutdown,
+    ) -> HttpResponse {
+        let mut response = Router::route(config, request).respond_to(request);
+
+        log::info!(
+            "{} {} {}",
+            request.mothod,
+            request.resource.path(),
+            response.status_code()
+        );
+
+        if served >= config.max_requests || shutdown.is_requested() {
+            response.headers_mut().insert("Connection", "close");
+        }
+
+        Self::add_server_headers(config, &mut response);
+
+        response
+    }
+
+    /// Response to a request that couldn't be parsed, after which the connection closes
+    pub(crate) fn reject(config: &ServerConfig, e: &ParseError) -> HttpResponse {
+        log::error!("Parse request error: {}", e);
+
+        Self::closing_response(config, HttpStatus::from(e))
+    }
+
+    /// Response to a client that took longer than `request_timeout` to send a request,
+    /// after which the connection closes
+    pub(crate) fn time_out(config: &ServerConfig) -> HttpResponse {
+        log::error!("Request timed out");
+
+        Self::closing_response(config, HttpStatus::REQUEST_TIMEOUT)
+    }
+
+    /// A bodiless error response that closes the connection
+    fn closing_response(config: &ServerConfig, status: HttpStatus) -> HttpResponse {
+        let mut response = HttpResponse::builder()
+            .status(status)
+            .header("Connection", "close")
+            .build();
+
+        Self::add_server_headers(config, &mut response);
+
+        response
+    }
 
-            Router::route(request, &mut stream);
+    fn add_server_headers(config: &ServerConfig, response: &mut HttpResponse) {
+        if let Some(server_header) = &config.server_header {
+            if !response.headers_mut().contains_key("Server") {
+                response
+                    .headers_mut()
+                    .append("Server", server_header.as_str());
+            }
         }
     }
+
+    /// Send, logging write errors such as a broken pipe
+    ///
+    /// Returns whether the connection can be used for another request.
+    fn send(response: HttpResponse, stream: &mut TcpStream) -> bool {
+        let keep_alive = response.keep_alive();
+
+        match response.send(stream) {
+            Ok(()) => keep_alive,
+            Err(e) => {
+                log::error!("Send response error: {}", e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod server_test {
+    use super::*;
+    use crate::testing::{self, Client, TestServer};
+    use std::fs;
+    use std::net::SocketAddr;
+    use std::thread::JoinHandle;
+    use std::time::Instant;
+
+    /// Serve with the backend `config` names through [`Server::serve`]
+    fn start(config: ServerConfig) -> TestServer {
+        TestServer::start(config, |listener, config, shutdown| {
+            let server = Server {
+                config: Arc::clone(config),
+                shutdown: shutdown.clone(),
+            };
+
+            server.serve(listener);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_closing_response() {
+        let config = ServerConfig::default();
+        let mut sent = Vec::new();
+
+        Server::reject(&config, &ParseError::HeadersTooLarge)
+            .send(&mut sent)
+            .unwrap();
+
+        let sent = String::from_utf8(sent).unwrap();
+
+        assert!(sent.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        assert!(sent.contains("\r\nConnection: close\r\n"));
+        assert!(sent.ends_with("\r\nContent-Length: 0\r\n\r\n"));
+        assert!(!sent.contains("Content-Type"));
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        let config = testing::config();
+        let health = fs::read(config.public_dir.join("health.html")).unwrap();
+        let index = fs::read(config.public_dir.join("index.html")).unwrap();
+        let server = start(config);
+        let mut client = server.connect();
+
+        client.send(b"GET /health HTTP/1.1\r\nHost: a\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\n");
+
+        for body in [health, index] {
+            let response = client.response();
+
+            assert_eq!(response.status, 200);
+            assert_eq!(response.header("Connection"), None);
+            assert_eq!(response.body, body);
+        }
+
+        assert!(!client.is_closed(Duration::from_millis(100)));
+
+        // HTTP/1.0 connections only persist when the client asks for it
+        client.send(b"GET /health HTTP/1.0\r\nConnection: keep-alive\r\n\r\n");
+
+        assert_eq!(client.response().header("Connection"), Some("keep-alive"));
+        assert!(!client.is_closed(Duration::from_millis(100)));
+
+        client.send(b"GET /health HTTP/1.0\r\n\r\n");
+
+        assert_eq!(client.response().header("Connection"), Some("close"));
+        assert!(client.is_closed(Duration::from_secs(1)));
+
+        server.stop();
+    }
+
+    #[test]
+    fn test_max_requests() {
+        let server = start(ServerConfig {
+            max_requests: 2,
+            ..testing::config()
+        });
+        let mut client = server.connect();
+
+        for _ in 0..3 {
+            client.get("/health");
+        }
+
+        assert_eq!(client.response().header("Connection"), None);
+        assert_eq!(client.response().header("Connection"), Some("close"));
+        assert!(client.is_closed(Duration::from_secs(1)));
+
+        server.stop();
+    }
+
+    #[test]
+    fn test_service_unavailable() {
+        let server = start(ServerConfig {
+            workers: 1,
+            queue_size: 0,
+            ..testing::config()
+        });
+        // The only worker keeps this connection until it closes, once it is up and
+        // waiting for one
+        let busy = loop {
+            let mut client = server.connect();
+
+            client.get("/health");
+
+            if client.response().status == 200 {
+                break client;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        let mut client = server.connect();
+
+        client.get("/health");
+
+        let response = client.response();
+
+        assert_eq!(response.status, 503);
+        assert_eq!(response.header("Retry-After"), Some("1"));
+        assert_eq!(response.header("Connection"), Some("close"));
+        assert!(client.is_closed(Duration::from_secs(1)));
+
+        drop(busy);
+        server.stop();
+    }
+
+    /// Start [`Server::run`] on a thread, returning the address it serves
+    fn spawn_run(config: ServerConfig) -> (SocketAddr, Shutdown, JoinHandle<io::Result<()>>) {
+        // `run` binds on its own, so reserve a port for it
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let server = Server::new(ServerConfig {
+            bind: vec![addr.to_string()],
+            ..config
+        });
+        let shutdown = server.shutdown_handle();
+        let running = thread::spawn(move || server.run());
+
+        while TcpStream::connect(addr).is_err() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        (addr, shutdown, running)
+    }
+
+    #[test]
+    fn test_graceful_shutdown() {
+        let config = ServerConfig {
+            request_timeout: Duration::from_secs(5),
+            ..testing::config()
+        };
+        let grace_period = config.grace_period;
+        let (addr, shutdown, running) = spawn_run(config);
+        let mut idle = Client::connect(addr);
+        let mut busy = Client::connect(addr);
+
+        idle.get("/health");
+        idle.response();
+        busy.send(b"GET /health HTTP/1.1\r\n");
+        thread::sleep(Duration::from_millis(100));
+
+        let start = Instant::now();
+
+        shutdown.shutdown();
+
+        assert!(idle.is_closed(Duration::from_secs(1)));
+
+        busy.send(b"Host: a\r\n\r\n");
+
+        let response = busy.response();
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("Connection"), Some("close"));
+        assert!(busy.is_closed(Duration::from_secs(1)));
+
+        running.join().unwrap().unwrap();
+
+        assert!(start.elapsed() < grace_period);
+    }
+
+    #[test]
+    fn test_shutdown_grace_period() {
+        let config = ServerConfig {
+            request_timeout: Duration::from_secs(5),
+            grace_period: Duration::from_millis(300),
+            ..testing::config()
+        };
+        let grace_period = config.grace_period;
+        let (addr, shutdown, running) = spawn_run(config);
+        let mut stuck = Client::connect(addr);
+
+        stuck.send(b"GET /health HTTP/1.1\r\n");
+        thread::sleep(Duration::from_millis(100));
+
+        let start = Instant::now();
+
+        shutdown.shutdown();
+        running.join().unwrap().unwrap();
+
+        // The request that never finishes is given up on once the grace period is over
+        assert!(start.elapsed() >= grace_period);
+        assert!(start.elapsed() < grace_period + Duration::from_secs(1));
+    }
 }
@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Handle to stop a running [`Server`](crate::server::Server)
+///
+/// Once shutdown is requested the server stops accepting connections, closes the ones
+/// waiting idle between requests and answers requests still in flight with
+/// `Connection: close`. Whatever hasn't finished when the grace period runs out is
+/// dropped.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    requested: AtomicBool,
+    requested_at: Mutex<Option<Instant>>,
+    /// Called once shutdown is requested, to wake up backends blocked waiting for I/O
+    wakers: Mutex<Vec<Box<dyn Fn() + Send>>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        Shutdown::default()
+    }
+
+    /// Request shutdown; requesting it again changes nothing
+    pub fn shutdown(&self) {
+        let mut requested_at = self.inner.requested_at.lock().unwrap();
+
+        if requested_at.is_some() {
+            return;
+        }
+
+        *requested_at = Some(Instant::now());
+        self.inner.requested.store(true, Ordering::SeqCst);
+
+        for wake in self.inner.wakers.lock().unwrap().iter() {
+            wake();
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    /// When the grace period after a shutdown request runs out
+    pub(crate) fn deadline(&self, grace_period: Duration) -> Option<Instant> {
+        self.inner
+            .requested_at
+            .lock()
+            .unwrap()
+            .map(|requested_at| requested_at + grace_period)
+    }
+
+    /// Run `wake` once shutdown is requested, right away if it already was
+    pub(crate) fn on_shutdown(&self, wake: impl Fn() + Send + 'static) {
+        // Holding the lock keeps a concurrent request from missing the new waker
+        let requested_at = self.inner.requested_at.lock().unwrap();
+
+        if requested_at.is_some() {
+            wake();
+        } else {
+            self.inner.wakers.lock().unwrap().push(Box::new(wake));
+        }
+    }
+}
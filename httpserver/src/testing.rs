@@ -0,0 +1,198 @@
+//! Helpers for tests that run a backend on a real socket
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::server::ServerConfig;
+use crate::shutdown::Shutdown;
+
+/// How long a client waits for the server before the test fails
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The default settings with timeouts short enough for tests to run into
+pub fn config() -> ServerConfig {
+    ServerConfig {
+        bind: vec!["127.0.0.1:0".into()],
+        keep_alive_timeout: Duration::from_secs(2),
+        request_timeout: Duration::from_millis(300),
+        write_timeout: Duration::from_secs(2),
+        grace_period: Duration::from_secs(2),
+        workers: 2,
+        ..ServerConfig::default()
+    }
+}
+
+/// A directory of its own, named after the test, holding the pages of the public
+/// directory and `files`
+pub fn public_dir(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+    let dir = env::temp_dir().join(format!("httpserver-{}-{}", name, std::process::id()));
+    let pages = ServerConfig::default().public_dir;
+
+    fs::create_dir_all(&dir).unwrap();
+
+    for page in fs::read_dir(pages).unwrap() {
+        let page = page.unwrap();
+
+        fs::copy(page.path(), dir.join(page.file_name())).unwrap();
+    }
+
+    for (name, content) in files {
+        fs::write(dir.join(name), content).unwrap();
+    }
+
+    dir
+}
+
+/// A backend serving an ephemeral port from a thread of its own
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub shutdown: Shutdown,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl TestServer {
+    pub fn start<F>(config: ServerConfig, serve: F) -> TestServer
+    where
+        F: FnOnce(TcpListener, &Arc<ServerConfig>, &Shutdown) -> io::Result<()> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = Shutdown::new();
+        let handle = {
+            let config = Arc::new(config);
+            let shutdown = shutdown.clone();
+
+            thread::spawn(move || serve(listener, &config, &shutdown))
+        };
+
+        TestServer {
+            addr,
+            shutdown,
+            handle,
+        }
+    }
+
+    pub fn connect(&self) -> Client {
+        Client::connect(self.addr)
+    }
+
+    /// Request shutdown and wait for the backend to drain
+    pub fn stop(self) {
+        self.shutdown.shutdown();
+        self.join();
+    }
+
+    /// Wait for the backend to return, which it does once it has drained after shutdown
+    pub fn join(self) {
+        self.handle.join().unwrap().unwrap();
+    }
+}
+
+/// A client connection reading responses one at a time
+pub struct Client {
+    reader: BufReader<TcpStream>,
+}
+
+impl Client {
+    pub fn connect(addr: SocketAddr) -> Client {
+        let stream = TcpStream::connect(addr).unwrap();
+
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+
+        Client {
+            reader: BufReader::new(stream),
+        }
+    }
+
+    pub fn send(&mut self, bytes: &[u8]) {
+        self.reader.get_mut().write_all(bytes).unwrap();
+    }
+
+    /// `GET path` over HTTP/1.1
+    pub fn get(&mut self, path: &str) {
+        self.send(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes());
+    }
+
+    /// Read the next response, framed by `Content-Length` or else by the connection
+    /// closing
+    pub fn response(&mut self) -> Response {
+        let mut status_line = String::new();
+
+        self.reader.read_line(&mut status_line).unwrap();
+
+        let status = match status_line.split(' ').nth(1) {
+            Some(status) => status.parse().unwrap(),
+            None => panic!("no response, got {:?}", status_line),
+        };
+        let mut headers = Vec::new();
+
+        loop {
+            let mut line = String::new();
+
+            self.reader.read_line(&mut line).unwrap();
+
+            match line.trim_end().split_once(": ") {
+                Some((name, value)) => headers.push((name.to_string(), value.to_string())),
+                None => break,
+            }
+        }
+
+        let mut response = Response {
+            status,
+            headers,
+            body: Vec::new(),
+        };
+
+        match response.header("Content-Length") {
+            Some(length) => {
+                response.body.resize(length.parse().unwrap(), 0);
+                self.reader.read_exact(&mut response.body).unwrap();
+            }
+            None => {
+                self.reader.read_to_end(&mut response.body).unwrap();
+            }
+        }
+
+        response
+    }
+
+    /// Whether the server closed the connection, waiting for it at most `timeout`
+    pub fn is_closed(&mut self, timeout: Duration) -> bool {
+        let stream = self.reader.get_ref();
+
+        stream.set_read_timeout(Some(timeout)).unwrap();
+
+        let closed = match self.reader.fill_buf() {
+            Ok(buf) => buf.is_empty(),
+            Err(e) => e.kind() == io::ErrorKind::ConnectionReset,
+        };
+
+        self.reader
+            .get_ref()
+            .set_read_timeout(Some(CLIENT_TIMEOUT))
+            .unwrap();
+
+        closed
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
@@ -0,0 +1,864 @@
+use std::fs::File;
+use std::io::{self, Take};
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use http::request::{ParseStatus, RequestParser};
+use io_uring::{opcode, squeue, types, IoUring};
+
+use crate::log;
+use crate::server::{Server, ServerConfig};
+use crate::shutdown::Shutdown;
+
+const RING_ENTRIES: u32 = 256;
+
+/// Accepts kept in flight on the listener, so a burst of clients is taken in one go
+const ACCEPT_BATCH: usize = 16;
+
+/// Registered buffers per ring that file bodies are copied through
+const FILE_BUFFERS: usize = 16;
+const FILE_BUFFER_SIZE: usize = 64 * 1024;
+
+const RECV_BUFFER_SIZE: usize = 4096;
+
+/// Interval of the timer that checks connection timeouts and shutdown
+const TICK: Duration = Duration::from_secs(1);
+
+/// What a completion belongs to, kept in the low bits of its user data
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Accept,
+    Tick,
+    Cancel,
+    Recv,
+    Send,
+    ReadFile,
+    SendFile,
+}
+
+impl Op {
+    const ALL: [Op; 7] = [
+        Op::Accept,
+        Op::Tick,
+        Op::Cancel,
+        Op::Recv,
+        Op::Send,
+        Op::ReadFile,
+        Op::SendFile,
+    ];
+
+    fn user_data(self, id: usize) -> u64 {
+        (id as u64) << 8 | self as u64
+    }
+
+    fn from_user_data(user_data: u64) -> (Op, usize) {
+        (
+            Op::ALL[(user_data & 0xff) as usize],
+            (user_data >> 8) as usize,
+        )
+    }
+}
+
+/// Serve the listener from `config.workers` rings until they have drained after
+/// shutdown, or one of them fails
+///
+/// Like the event loops, every ring accepts on its own clone of the listener and keeps
+/// the connections it accepted. Rings notice a shutdown request on their next tick.
+pub fn run(listener: TcpListener, config: &ServerConfig, shutdown: &Shutdown) -> io::Result<()> {
+    thread::scope(|scope| {
+        let rings = (0..config.workers)
+            .map(|_| {
+                let listener = listener.try_clone()?;
+                let shutdown = shutdown.clone();
+
+                Ok(scope.spawn(move || Ring::new(listener, config, shutdown)?.run()))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        for ring in rings {
+            ring.join().unwrap()?;
+        }
+
+        Ok(())
+    })
+}
+
+struct Ring<'a> {
+    // Declared before `buffers`, so the ring, and its registration, goes away first
+    ring: IoUring,
+    listener: TcpListener,
+    /// Connections by id, a free slot being reused by the next accepted connection
+    connections: Vec<Option<Connection>>,
+    free_ids: Vec<usize>,
+    buffers: FileBuffers,
+    /// Accepts currently in flight
+    accepting: usize,
+    /// Read by the kernel while the tick timer is armed, hence boxed
+    tick: Box<types::Timespec>,
+    config: &'a ServerConfig,
+    shutdown: Shutdown,
+    /// Shutdown was requested: accepts are no longer rearmed
+    draining: bool,
+}
+
+impl<'a> Ring<'a> {
+    fn new(
+        listener: TcpListener,
+        config: &'a ServerConfig,
+        shutdown: Shutdown,
+    ) -> io::Result<Ring<'a>> {
+        let ring = IoUring::new(RING_ENTRIES)?;
+        let buffers = FileBuffers::new();
+
+        // SAFETY: the buffers outlive the ring, see the field order of `Ring`.
+        unsafe { ring.submitter().register_buffers(&buffers.iovecs())? };
+
+        Ok(Ring {
+            ring,
+            listener,
+            connections: Vec::new(),
+            free_ids: Vec::new(),
+            buffers,
+            accepting: 0,
+            tick: Box::new(types::Timespec::from(TICK)),
+            config,
+            shutdown,
+            draining: false,
+        })
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        self.accept()?;
+        self.arm_tick()?;
+
+        loop {
+            // One syscall both submits everything queued since the last round and waits
+            match self.ring.submit_and_wait(1) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => result?,
+            };
+
+            let completions: Vec<(u64, i32)> = self
+                .ring
+                .completion()
+                .map(|cqe| (cqe.user_data(), cqe.result()))
+                .collect();
+
+            for (user_data, result) in completions {
+                self.complete(user_data, result)?;
+            }
+
+            // Only once no connection has an operation left in flight that could still
+            // use its buffers
+            if self.draining && self.connections.iter().all(Option::is_none) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Top the accepts in flight back up to `ACCEPT_BATCH`
+    fn accept(&mut self) -> io::Result<()> {
+        while !self.draining && self.accepting < ACCEPT_BATCH {
+            let entry = opcode::Accept::new(
+                types::Fd(self.listener.as_raw_fd()),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+            .build()
+            .user_data(Op::Accept.user_data(0));
+
+            self.push(&entry)?;
+            self.accepting += 1;
+        }
+
+        Ok(())
+    }
+
+    fn arm_tick(&mut self) -> io::Result<()> {
+        let entry = opcode::Timeout::new(&*self.tick)
+            .build()
+            .user_data(Op::Tick.user_data(0));
+
+        self.push(&entry)
+    }
+
+    fn complete(&mut self, user_data: u64, result: i32) -> io::Result<()> {
+        let (op, id) = Op::from_user_data(user_data);
+
+        match op {
+            Op::Accept => {
+                self.accepting -= 1;
+
+                if result < 0 {
+                    // e.g. out of file descriptors, retried on the next tick
+                    log::error!(
+                        "Accept connection error: {}",
+                        io::Error::from_raw_os_error(-result)
+                    );
+                    return Ok(());
+                }
+
+                // SAFETY: the kernel just handed us this new descriptor.
+                let stream = unsafe { TcpStream::from_raw_fd(result) };
+
+                // Accepts armed before shutdown may still complete
+                if self.draining {
+                    return Ok(());
+                }
+
+                let id = self.insert(Connection::new(stream, self.config));
+
+                self.accept()?;
+                self.advance(id)
+            }
+            Op::Tick => {
+                self.check_timeouts()?;
+
+                if self.shutdown.is_requested() {
+                    self.drain()?;
+                }
+
+                self.accept()?;
+                self.arm_tick()
+            }
+            Op::Cancel => Ok(()),
+            _ => {
+                let Some(connection) = self.connections[id].as_mut() else {
+                    return Ok(());
+                };
+
+                connection.in_flight = None;
+
+                if connection.cancelled {
+                    self.remove(id);
+                    return Ok(());
+                }
+
+                if connection.timed_out {
+                    connection.respond_timeout(self.config);
+                    return self.advance(id);
+                }
+
+                match connection.complete(op, result, &mut self.buffers) {
+                    Ok(()) => self.advance(id),
+                    Err(e) => {
+                        log::error!("Connection error: {}", e);
+                        self.close(id)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Queue the next operation of an idle connection, or close it when it is done
+    fn advance(&mut self, id: usize) -> io::Result<()> {
+        let Some(connection) = self.connections[id].as_mut() else {
+            return Ok(());
+        };
+
+        match connection.next(id, self.config, &self.shutdown, &mut self.buffers) {
+            Some(entry) => {
+                connection.in_flight = Some(entry.get_user_data());
+                self.push(&entry)
+            }
+            None => self.close(id),
+        }
+    }
+
+    fn check_timeouts(&mut self) -> io::Result<()> {
+        let expired: Vec<(usize, Expiry)> = self
+            .connections
+            .iter()
+            .enumerate()
+            .filter_map(|(id, connection)| Some((id, connection.as_ref()?.expiry(self.config)?)))
+            .collect();
+
+        for (id, expiry) in expired {
+            match expiry {
+                Expiry::Close => self.close(id)?,
+                Expiry::Request => self.time_out(id)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stop accepting and close the connections waiting idle, or every connection once
+    /// the grace period has run out
+    fn drain(&mut self) -> io::Result<()> {
+        let expired = self
+            .shutdown
+            .deadline(self.config.grace_period)
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        let mut dropped = 0;
+        let closing: Vec<usize> = self
+            .connections
+            .iter()
+            .enumerate()
+            .filter_map(|(id, connection)| {
+                let connection = connection.as_ref()?;
+
+                if connection.cancelled {
+                    return None;
+                }
+
+                if !connection.is_idle() {
+                    dropped += 1;
+                }
+
+                (expired || connection.is_idle()).then_some(id)
+            })
+            .collect();
+
+        self.draining = true;
+
+        if expired && dropped > 0 {
+            log::error!("Dropping {} connections after the grace period", dropped);
+        }
+
+        for id in closing {
+            self.close(id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Stop waiting for the rest of a request, to answer 408 once the recv is cancelled
+    fn time_out(&mut self, id: usize) -> io::Result<()> {
+        let Some(connection) = self.connections[id].as_mut() else {
+            return Ok(());
+        };
+        let Some(user_data) = connection.in_flight else {
+            return Ok(());
+        };
+
+        connection.timed_out = true;
+
+        let entry = opcode::AsyncCancel::new(user_data)
+            .build()
+            .user_data(Op::Cancel.user_data(id));
+
+        self.push(&entry)
+    }
+
+    /// Close a connection; one with an operation in flight is cancelled first and only
+    /// dropped once that operation completes, as the kernel may still use its buffers
+    fn close(&mut self, id: usize) -> io::Result<()> {
+        let Some(connection) = self.connections[id].as_mut() else {
+            return Ok(());
+        };
+
+        match connection.in_flight {
+            Some(user_data) => {
+                connection.cancelled = true;
+
+                let entry = opcode::AsyncCancel::new(user_data)
+                    .build()
+                    .user_data(Op::Cancel.user_data(id));
+
+                self.push(&entry)
+            }
+            None => {
+                self.remove(id);
+                Ok(())
+            }
+        }
+    }
+
+    fn insert(&mut self, connection: Connection) -> usize {
+        match self.free_ids.pop() {
+            Some(id) => {
+                self.connections[id] = Some(connection);
+                id
+            }
+            None => {
+                self.connections.push(Some(connection));
+                self.connections.len() - 1
+            }
+        }
+    }
+
+    fn remove(&mut self, id: usize) {
+        if let Some(connection) = self.connections[id].take() {
+            connection.release(&mut self.buffers);
+            self.free_ids.push(id);
+        }
+    }
+
+    /// Queue an entry, submitting what is queued already if the submission queue is full
+    fn push(&mut self, entry: &squeue::Entry) -> io::Result<()> {
+        loop {
+            // SAFETY: every buffer an entry points to is owned by the ring or by a
+            // connection that is only dropped once none of its operations is in flight.
+            if unsafe { self.ring.submission().push(entry) }.is_ok() {
+                return Ok(());
+            }
+
+            self.ring.submit()?;
+        }
+    }
+}
+
+/// Fixed buffers registered with the ring, so file reads and socket writes skip
+/// mapping the pages on every operation
+struct FileBuffers {
+    memory: Vec<u8>,
+    free: Vec<u16>,
+}
+
+impl FileBuffers {
+    fn new() -> FileBuffers {
+        FileBuffers {
+            memory: vec![0; FILE_BUFFERS * FILE_BUFFER_SIZE],
+            free: (0..FILE_BUFFERS as u16).collect(),
+        }
+    }
+
+    fn iovecs(&self) -> Vec<libc::iovec> {
+        (0..FILE_BUFFERS)
+            .map(|index| libc::iovec {
+                iov_base: self.memory[index * FILE_BUFFER_SIZE..].as_ptr() as *mut _,
+                iov_len: FILE_BUFFER_SIZE,
+            })
+            .collect()
+    }
+
+    fn take(&mut self) -> Option<u16> {
+        self.free.pop()
+    }
+
+    fn give_back(&mut self, index: u16) {
+        self.free.push(index);
+    }
+
+    fn ptr(&mut self, index: u16) -> *mut u8 {
+        self.memory[index as usize * FILE_BUFFER_SIZE..].as_mut_ptr()
+    }
+}
+
+/// A file body on its way to the socket through a registered buffer
+struct FileBody {
+    file: File,
+    /// Bytes of the file still to be read
+    remaining: u64,
+    offset: u64,
+    buffer: Option<u16>,
+    /// Bytes read into the buffer, and how many of them have been sent
+    filled: usize,
+    sent: usize,
+}
+
+/// A timeout a connection ran into
+enum Expiry {
+    /// Idle for too long, or stuck writing
+    Close,
+    /// Too slow to send a request, which earns a 408
+    Request,
+}
+
+/// One client connection, with at most one operation in flight at a time
+struct Connection {
+    stream: TcpStream,
+    /// Parser for the request being received, which resumes where the last read stopped
+    parser: RequestParser,
+    read_buffer: Vec<u8>,
+    /// Target of the recv in flight
+    recv_buffer: Box<[u8]>,
+    /// Serialized response head, or whole response without a file body
+    write_buffer: Vec<u8>,
+    written: usize,
+    file: Option<FileBody>,
+    served: usize,
+    /// The client has stopped sending
+    read_closed: bool,
+    /// The last response asked for the connection to be closed
+    closing: bool,
+    /// User data of the operation in flight
+    in_flight: Option<u64>,
+    /// Closed while an operation was in flight, waiting for it to complete
+    cancelled: bool,
+    /// Timed out while receiving a request, waiting for the recv to be cancelled
+    timed_out: bool,
+    /// When the first byte of the request being received arrived
+    request_start: Option<Instant>,
+    last_active: Instant,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, config: &ServerConfig) -> Connection {
+        Connection {
+            stream,
+            parser: RequestParser::with_config(config.parser.clone()),
+            read_buffer: Vec::new(),
+            recv_buffer: vec![0; RECV_BUFFER_SIZE].into_boxed_slice(),
+            write_buffer: Vec::new(),
+            written: 0,
+            file: None,
+            served: 0,
+            read_closed: false,
+            closing: false,
+            in_flight: None,
+            cancelled: false,
+            timed_out: false,
+            request_start: None,
+            last_active: Instant::now(),
+        }
+    }
+
+    /// Waiting for a next request that hasn't started to arrive
+    fn is_idle(&self) -> bool {
+        let receiving = self
+            .in_flight
+            .is_some_and(|user_data| Op::from_user_data(user_data).0 == Op::Recv);
+
+        receiving && self.request_start.is_none() && !self.timed_out
+    }
+
+    /// The timeout the connection has run into, if any
+    ///
+    /// Like in the event loop backend, a client gets `request_timeout` to send a request
+    /// once it has started, and a socket that doesn't take a response for
+    /// `write_timeout` drops the connection.
+    fn expiry(&self, config: &ServerConfig) -> Option<Expiry> {
+        if self.cancelled || self.timed_out {
+            return None;
+        }
+
+        let idle = self.last_active.elapsed();
+
+        match self
+            .in_flight
+            .map(|user_data| Op::from_user_data(user_data).0)
+        {
+            Some(Op::Recv) => match self.request_start {
+                Some(start) if start.elapsed() >= config.request_timeout => Some(Expiry::Request),
+                None if idle >= config.keep_alive_timeout => Some(Expiry::Close),
+                _ => None,
+            },
+            Some(Op::Send | Op::SendFile) if idle >= config.write_timeout => Some(Expiry::Close),
+            _ => None,
+        }
+    }
+
+    /// Replace the request being received with a 408, after which the connection closes
+    fn respond_timeout(&mut self, config: &ServerConfig) {
+        self.timed_out = false;
+        self.closing = true;
+
+        if let Err(e) = Server::time_out(config).send(&mut self.write_buffer) {
+            log::error!("Send response error: {}", e);
+        }
+    }
+
+    /// Account for a completed operation
+    fn complete(&mut self, op: Op, result: i32, buffers: &mut FileBuffers) -> io::Result<()> {
+        if result < 0 {
+            return Err(io::Error::from_raw_os_error(-result));
+        }
+
+        let len = result as usize;
+
+        self.last_active = Instant::now();
+
+        match op {
+            Op::Recv if len == 0 => self.read_closed = true,
+            Op::Recv => {
+                self.read_buffer.extend_from_slice(&self.recv_buffer[..len]);
+                self.request_start.get_or_insert(self.last_active);
+            }
+            Op::Send => self.written += len,
+            Op::ReadFile => {
+                let body = self.file.as_mut().expect("a file is being sent");
+
+                // The file shrank after its length was sent
+                if len == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+
+                body.remaining -= len as u64;
+                body.offset += len as u64;
+
+                if body.buffer.is_some() {
+                    body.filled = len;
+                    body.sent = 0;
+                } else {
+                    // Read into the write buffer, for lack of a registered one
+                    self.write_buffer.truncate(len);
+
+                    if body.remaining == 0 {
+                        self.file = None;
+                    }
+                }
+            }
+            Op::SendFile => {
+                let body = self.file.as_mut().expect("a file is being sent");
+
+                body.sent += len;
+
+                if body.sent == body.filled && body.remaining == 0 {
+                    if let Some(index) = body.buffer {
+                        buffers.give_back(index);
+                    }
+
+                    self.file = None;
+                }
+            }
+            Op::Accept | Op::Tick | Op::Cancel => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    /// The next operation to queue, or `None` once the connection should be closed
+    ///
+    /// Pipelined requests are answered one by one, and the next one is only parsed once
+    /// the previous response has been written, as in the event loop backend.
+    fn next(
+        &mut self,
+        id: usize,
+        config: &ServerConfig,
+        shutdown: &Shutdown,
+        buffers: &mut FileBuffers,
+    ) -> Option<squeue::Entry> {
+        loop {
+            if self.written < self.write_buffer.len() {
+                return Some(self.send_pending(id));
+            }
+
+            self.write_buffer.clear();
+            self.written = 0;
+
+            if self.file.is_some() {
+                return self.next_file(id, buffers);
+            }
+
+            if self.closing {
+                return None;
+            }
+
+            let response = match self.parser.parse(&self.read_buffer) {
+                Ok(ParseStatus::Complete(request, consumed)) => {
+                    self.read_buffer.drain(..consumed);
+                    self.request_start = (!self.read_buffer.is_empty()).then(Instant::now);
+                    self.served += 1;
+
+                    Server::respond(config, &request, self.served, shutdown)
+                }
+                Ok(ParseStatus::Incomplete) if self.read_closed => return None,
+                Ok(ParseStatus::Incomplete) => {
+                    let entry = opcode::Recv::new(
+                        types::Fd(self.stream.as_raw_fd()),
+                        self.recv_buffer.as_mut_ptr(),
+                        self.recv_buffer.len() as u32,
+                    );
+
+                    return Some(entry.build().user_data(Op::Recv.user_data(id)));
+                }
+                Err(e) => Server::reject(config, &e),
+            };
+
+            self.closing = !response.keep_alive();
+
+            match response.send_head(&mut self.write_buffer) {
+                Ok(Some(file)) => self.start_file(file),
+                Ok(None) => {}
+                Err(e) => log::error!("Send response error: {}", e),
+            }
+        }
+    }
+
+    /// Send a file limited to its `Content-Length`, or to its end for a body that runs
+    /// until the connection closes
+    fn start_file(&mut self, file: Take<File>) {
+        let remaining = file.limit();
+
+        if remaining == 0 {
+            return;
+        }
+
+        self.file = Some(FileBody {
+            file: file.into_inner(),
+            remaining,
+            offset: 0,
+            buffer: None,
+            filled: 0,
+            sent: 0,
+        });
+    }
+
+    fn send_pending(&self, id: usize) -> squeue::Entry {
+        let pending = &self.write_buffer[self.written..];
+        let entry = opcode::Send::new(
+            types::Fd(self.stream.as_raw_fd()),
+            pending.as_ptr(),
+            pending.len() as u32,
+        );
+
+        entry.build().user_data(Op::Send.user_data(id))
+    }
+
+    fn next_file(&mut self, id: usize, buffers: &mut FileBuffers) -> Option<squeue::Entry> {
+        let body = self.file.as_mut().expect("a file is being sent");
+
+        if body.buffer.is_none() {
+            body.buffer = buffers.take();
+        }
+
+        let Some(index) = body.buffer else {
+            // Every registered buffer is busy: read into the write buffer instead, which
+            // is then sent like a response head
+            self.write_buffer
+                .resize((body.remaining as usize).min(FILE_BUFFER_SIZE), 0);
+
+            let entry = opcode::Read::new(
+                types::Fd(body.file.as_raw_fd()),
+                self.write_buffer.as_mut_ptr(),
+                self.write_buffer.len() as u32,
+            )
+            .offset(body.offset);
+
+            return Some(entry.build().user_data(Op::ReadFile.user_data(id)));
+        };
+
+        let buffer = buffers.ptr(index);
+
+        if body.sent < body.filled {
+            let entry = opcode::WriteFixed::new(
+                types::Fd(self.stream.as_raw_fd()),
+                buffer.wrapping_add(body.sent),
+                (body.filled - body.sent) as u32,
+                index,
+            );
+
+            return Some(entry.build().user_data(Op::SendFile.user_data(id)));
+        }
+
+        let len = body.remaining.min(FILE_BUFFER_SIZE as u64) as u32;
+        let entry = opcode::ReadFixed::new(types::Fd(body.file.as_raw_fd()), buffer, len, index)
+            .offset(body.offset);
+
+        Some(entry.build().user_data(Op::ReadFile.user_data(id)))
+    }
+
+    fn release(self, buffers: &mut FileBuffers) {
+        if let Some(FileBody {
+            buffer: Some(index),
+            ..
+        }) = self.file
+        {
+            buffers.give_back(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod uring_test {
+    use super::*;
+    use crate::testing::{self, TestServer};
+    use std::fs;
+
+    /// Whether the kernel lets us set up a ring at all; tests pass vacuously if not
+    fn supported() -> bool {
+        let supported = IoUring::new(2).is_ok();
+
+        if !supported {
+            eprintln!("io_uring is unavailable, skipping");
+        }
+
+        supported
+    }
+
+    fn start(config: ServerConfig) -> TestServer {
+        TestServer::start(config, |listener, config, shutdown| {
+            run(listener, config, shutdown)
+        })
+    }
+
+    #[test]
+    fn test_pipelined() {
+        if !supported() {
+            return;
+        }
+
+        let config = testing::config();
+        let health = fs::read(config.public_dir.join("health.html")).unwrap();
+        let index = fs::read(config.public_dir.join("index.html")).unwrap();
+        let server = start(config);
+        let mut client = server.connect();
+
+        client.send(b"GET /health HTTP/1.1\r\nHost: a\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\n");
+
+        assert_eq!(client.response().body, health);
+        assert_eq!(client.response().body, index);
+
+        server.stop();
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        if !supported() {
+            return;
+        }
+
+        let server = start(testing::config());
+        let mut client = server.connect();
+
+        client.send(b"GET /health HTTP/1.1\r\nHo");
+
+        let response = client.response();
+
+        assert_eq!(response.status, 408);
+        assert_eq!(response.header("Connection"), Some("close"));
+        assert!(client.is_closed(Duration::from_secs(1)));
+
+        server.stop();
+    }
+
+    /// Files spanning many registered buffers, sent to more clients at once than there
+    /// are buffers, so that some of them are read through the write buffer instead
+    #[test]
+    fn test_file_buffers() {
+        if !supported() {
+            return;
+        }
+
+        let big = (0..16 * FILE_BUFFER_SIZE + 100)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let public_dir = testing::public_dir("uring-files", &[("big.bin", &big)]);
+        let server = start(ServerConfig {
+            public_dir: public_dir.clone(),
+            workers: 1,
+            ..testing::config()
+        });
+        let mut clients = (0..FILE_BUFFERS + 4)
+            .map(|_| server.connect())
+            .collect::<Vec<_>>();
+
+        // Nobody reads until every response is under way
+        for client in &mut clients {
+            client.get("/big.bin");
+        }
+
+        thread::sleep(Duration::from_millis(200));
+
+        for client in &mut clients {
+            let response = client.response();
+
+            assert_eq!(response.status, 200);
+            assert!(response.body == big);
+        }
+
+        // The buffers all went back, and a file still goes through one
+        let mut client = server.connect();
+
+        client.get("/big.bin");
+
+        assert!(client.response().body == big);
+
+        server.stop();
+        fs::remove_dir_all(public_dir).unwrap();
+    }
+}